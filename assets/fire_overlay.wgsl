@@ -14,6 +14,7 @@ struct Fire {
 
 struct Fires {
     fires: array<Fire,MAX_FIRES>;
+    darkness: f32;
 };
 
 [[group(1), binding(0)]]
@@ -28,7 +29,7 @@ fn circle(st: vec2<f32>, center: vec2<f32>, radius: f32) -> f32{
 }
 [[stage(fragment)]]
 fn fragment(in: VertexOutput) -> [[location(0)]]vec4<f32> {
-    var color = vec4<f32>(0.0,0.0,0.0, 0.99);
+    var color = vec4<f32>(0.0,0.0,0.0, fires.darkness);
     for( var i: i32 = 0; i < MAX_FIRES; i= i +1) {
         color = color * (1.0-circle(in.world_position.xy, fires.fires[i].position, fires.fires[i].strength ));
     }
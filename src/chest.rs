@@ -0,0 +1,159 @@
+use bevy::prelude::*;
+
+use crate::{
+    graphics::PlaceHolderGraphics,
+    harvest::{nearest_interactable, HarvestTable},
+    inventory::{spawn_slot_box, Inventory, Slot, BOX_SPACING, HOTBAR_Y},
+    items::{Object, WorldObject},
+    player::{Player, INTERACT_KEY},
+    GameCamera,
+};
+
+pub const CHEST_SIZE: usize = 20;
+/// The chest's slots are laid out left of the inventory, this many to a row
+const CHEST_COLUMNS: usize = 4;
+const CHEST_GRID_X: f32 = -740.0;
+
+/// The chest the player has open, its slots can be used like the player's own
+#[derive(Default)]
+pub struct ChestMenu {
+    pub chest: Option<Entity>,
+}
+
+/// Holds the open chest's slot boxes
+#[derive(Component)]
+pub struct UiChestGrid;
+
+pub struct ChestPlugin;
+
+impl Plugin for ChestPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ChestMenu>()
+            .add_system(fill_new_chests)
+            .add_system(open_chests.label("chestinput"))
+            .add_system(close_chests);
+    }
+}
+
+/// The open chest's slots, left out when running headless
+pub struct ChestUiPlugin;
+
+impl Plugin for ChestUiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(update_chest_grid);
+    }
+}
+
+/// Newly placed chests start empty, loaded ones already have their contents
+fn fill_new_chests(
+    mut commands: Commands,
+    object_query: Query<(Entity, &Object), (Changed<Object>, Without<Inventory>)>,
+) {
+    for (ent, object) in object_query.iter() {
+        if object.object == WorldObject::Chest {
+            commands.entity(ent).insert(Inventory::new(CHEST_SIZE));
+        }
+    }
+}
+
+/// Interacting with the closest chest opens it, interacting again or Escape closes it
+fn open_chests(
+    keyboard: Res<Input<KeyCode>>,
+    table: Res<HarvestTable>,
+    mut menu: ResMut<ChestMenu>,
+    player_query: Query<(&Transform, &Player)>,
+    object_query: Query<(Entity, &Transform, &Object), Without<Player>>,
+) {
+    if menu.chest.is_some() {
+        if keyboard.just_pressed(INTERACT_KEY) || keyboard.just_pressed(KeyCode::Escape) {
+            menu.chest = None;
+        }
+        return;
+    }
+    if !keyboard.just_pressed(INTERACT_KEY) {
+        return;
+    }
+    let (player_transform, player) = player_query.single();
+    let player_pos = player_transform.translation.truncate();
+
+    let objects = object_query
+        .iter()
+        .map(|(ent, transform, object)| (ent, transform.translation.truncate(), object.object));
+    let nearest = nearest_interactable(&table, player, player_pos, objects);
+
+    if let Some((ent, _, object)) = nearest.and_then(|ent| object_query.get(ent).ok()) {
+        if object.object == WorldObject::Chest {
+            menu.chest = Some(ent);
+        }
+    }
+}
+
+/// Walking out of reach closes the chest, so does it being put away with its chunk
+fn close_chests(
+    mut menu: ResMut<ChestMenu>,
+    player_query: Query<(&Transform, &Player)>,
+    chest_query: Query<&Transform, With<Inventory>>,
+) {
+    let chest = match menu.chest {
+        Some(chest) => chest,
+        None => return,
+    };
+    let (player_transform, player) = player_query.single();
+    let in_reach = chest_query.get(chest).is_ok_and(|transform| {
+        transform
+            .translation
+            .truncate()
+            .distance(player_transform.translation.truncate())
+            < player.arm_length
+    });
+    if !in_reach {
+        menu.chest = None;
+    }
+}
+
+/// Builds the open chest's slots next to the inventory, and removes them once it's closed
+fn update_chest_grid(
+    mut commands: Commands,
+    graphics: Res<PlaceHolderGraphics>,
+    asset_server: Res<AssetServer>,
+    menu: Res<ChestMenu>,
+    camera_query: Query<Entity, With<GameCamera>>,
+    grid_query: Query<Entity, With<UiChestGrid>>,
+    inventory_query: Query<&Inventory>,
+) {
+    if !menu.is_changed() {
+        return;
+    }
+    for ent in grid_query.iter() {
+        commands.entity(ent).despawn_recursive();
+    }
+    let chest = match menu.chest {
+        Some(chest) => chest,
+        None => return,
+    };
+    let capacity = inventory_query
+        .get(chest)
+        .map_or(0, |inventory| inventory.capacity());
+    let boxes: Vec<Entity> = (0..capacity)
+        .map(|i| {
+            let column = (i % CHEST_COLUMNS) as f32;
+            let row = (i / CHEST_COLUMNS) as f32;
+            let position = Vec2::new(
+                CHEST_GRID_X + column * BOX_SPACING,
+                HOTBAR_Y + row * BOX_SPACING,
+            );
+            let slot = Slot {
+                owner: chest,
+                index: i,
+            };
+            spawn_slot_box(slot, position, &mut commands, &graphics, &asset_server)
+        })
+        .collect();
+    let grid = commands
+        .spawn_bundle((Transform::default(), GlobalTransform::default()))
+        .insert(UiChestGrid)
+        .insert(Name::new("Chest Grid"))
+        .push_children(&boxes)
+        .id();
+    commands.entity(camera_query.single()).add_child(grid);
+}
@@ -0,0 +1,251 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::read_asset;
+
+pub const CLOCK_PATH: &str = "clock.ron";
+/// How dark the overlay gets in the middle of the night, 1.0 would be pitch black
+pub const MAX_DARKNESS: f32 = 0.95;
+const DAY_COLOR: Color = Color::rgb(0.3, 0.5, 0.3);
+const NIGHT_COLOR: Color = Color::rgb(0.05, 0.1, 0.08);
+const ANNOUNCEMENT_SECONDS: f32 = 4.0;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum DayPhase {
+    Dawn,
+    Day,
+    Dusk,
+    Night,
+}
+
+/// Time of day, phases start at the given fraction of the day and last until the next one
+pub struct WorldClock {
+    /// Real seconds one full day takes
+    pub day_length: f32,
+    pub dawn_start: f32,
+    pub day_start: f32,
+    pub dusk_start: f32,
+    pub night_start: f32,
+    /// Fraction of the current day that has passed, from 0 to 1
+    pub time: f32,
+    /// Days that have fully passed
+    pub day: u32,
+    phase: DayPhase,
+}
+
+/// The tunable part of the clock as written in clock.ron
+#[derive(Deserialize)]
+struct ClockFile {
+    day_length: f32,
+    dawn_start: f32,
+    day_start: f32,
+    dusk_start: f32,
+    night_start: f32,
+}
+
+/// Reads the length of a day and its phases, so they can be tuned without recompiling
+pub fn load_world_clock(path: &str) -> WorldClock {
+    let contents = read_asset(path);
+    parse_world_clock(&contents)
+        .unwrap_or_else(|err| panic!("Invalid clock file {}: {}", path, err))
+}
+
+pub fn parse_world_clock(contents: &str) -> Result<WorldClock, String> {
+    let file: ClockFile = ron::de::from_str(contents).map_err(|err| err.to_string())?;
+    if !file.day_length.is_finite() || file.day_length <= 0.0 {
+        return Err("day_length has to be positive".to_string());
+    }
+    let starts = [
+        file.dawn_start,
+        file.day_start,
+        file.dusk_start,
+        file.night_start,
+    ];
+    if !starts.iter().all(|start| (0.0..1.0).contains(start)) {
+        return Err("phases have to start between 0 and 1".to_string());
+    }
+    if starts.windows(2).any(|pair| pair[0] >= pair[1]) {
+        return Err("dawn, day, dusk and night have to start in that order".to_string());
+    }
+    let mut clock = WorldClock {
+        day_length: file.day_length,
+        dawn_start: file.dawn_start,
+        day_start: file.day_start,
+        dusk_start: file.dusk_start,
+        night_start: file.night_start,
+        time: 0.0,
+        day: 0,
+        phase: DayPhase::Night,
+    };
+    //Start in the morning so the player can look around first
+    clock.set_time(clock.day_start, 0);
+    Ok(clock)
+}
+
+impl WorldClock {
+    pub fn phase(&self) -> DayPhase {
+        self.phase
+    }
+
+    /// Jumps to a time without announcing the phase, used when loading a save
+    pub fn set_time(&mut self, time: f32, day: u32) {
+        self.time = time.rem_euclid(1.0);
+        self.day = day;
+        self.phase = self.phase_at(self.time);
+    }
+
+    pub fn phase_at(&self, time: f32) -> DayPhase {
+        if time < self.dawn_start || time >= self.night_start {
+            DayPhase::Night
+        } else if time < self.day_start {
+            DayPhase::Dawn
+        } else if time < self.dusk_start {
+            DayPhase::Day
+        } else {
+            DayPhase::Dusk
+        }
+    }
+
+    /// From 0 during the day to MAX_DARKNESS at night, fading during dawn and dusk
+    pub fn darkness(&self) -> f32 {
+        let fade = match self.phase {
+            DayPhase::Day => 0.0,
+            DayPhase::Night => 1.0,
+            DayPhase::Dawn => 1.0 - progress(self.time, self.dawn_start, self.day_start),
+            DayPhase::Dusk => progress(self.time, self.dusk_start, self.night_start),
+        };
+        fade * MAX_DARKNESS
+    }
+
+    pub fn clear_color(&self) -> Color {
+        let t = self.darkness() / MAX_DARKNESS;
+        Color::rgb(
+            DAY_COLOR.r() + (NIGHT_COLOR.r() - DAY_COLOR.r()) * t,
+            DAY_COLOR.g() + (NIGHT_COLOR.g() - DAY_COLOR.g()) * t,
+            DAY_COLOR.b() + (NIGHT_COLOR.b() - DAY_COLOR.b()) * t,
+        )
+    }
+}
+
+fn progress(time: f32, start: f32, end: f32) -> f32 {
+    ((time - start) / (end - start)).clamp(0.0, 1.0)
+}
+
+/// Sent once whenever the clock moves into a new phase
+pub struct PhaseChanged {
+    pub phase: DayPhase,
+    pub day: u32,
+}
+
+#[derive(Component)]
+pub struct PhaseAnnouncement {
+    timer: Timer,
+}
+
+pub struct ClockPlugin;
+
+impl Plugin for ClockPlugin {
+    fn build(&self, app: &mut App) {
+        //A clock put in before the plugin is kept, like a seed given to the RngPlugin
+        if !app.world.contains_resource::<WorldClock>() {
+            app.insert_resource(load_world_clock(CLOCK_PATH));
+        }
+        let clear_color = app
+            .world
+            .get_resource::<WorldClock>()
+            .unwrap()
+            .clear_color();
+        app.insert_resource(ClearColor(clear_color))
+            .add_event::<PhaseChanged>()
+            .add_system(advance_clock.label("clock"))
+            .add_system(update_clear_color.after("clock"));
+    }
+}
+
+pub struct ClockUiPlugin;
+
+impl Plugin for ClockUiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(announce_phases.after("clock"));
+    }
+}
+
+fn advance_clock(
+    time: Res<Time>,
+    mut clock: ResMut<WorldClock>,
+    mut phase_events: EventWriter<PhaseChanged>,
+) {
+    clock.time += time.delta_seconds() / clock.day_length;
+    while clock.time >= 1.0 {
+        clock.time -= 1.0;
+        clock.day += 1;
+    }
+    let phase = clock.phase_at(clock.time);
+    if phase != clock.phase {
+        clock.phase = phase;
+        phase_events.send(PhaseChanged {
+            phase: phase,
+            day: clock.day,
+        });
+    }
+}
+
+fn update_clear_color(clock: Res<WorldClock>, mut clear_color: ResMut<ClearColor>) {
+    if clock.is_changed() {
+        clear_color.0 = clock.clear_color();
+    }
+}
+
+/// Tells the player when day breaks and night falls
+fn announce_phases(
+    mut commands: Commands,
+    time: Res<Time>,
+    asset_server: Res<AssetServer>,
+    mut phase_events: EventReader<PhaseChanged>,
+    mut announcement_query: Query<(Entity, &mut PhaseAnnouncement)>,
+) {
+    for (ent, mut announcement) in announcement_query.iter_mut() {
+        announcement.timer.tick(time.delta());
+        if announcement.timer.finished() {
+            commands.entity(ent).despawn_recursive();
+        }
+    }
+
+    for changed in phase_events.iter() {
+        info!("Day {}: {:?}", changed.day + 1, changed.phase);
+        let message = match changed.phase {
+            DayPhase::Dawn => format!("Day {} is dawning", changed.day + 1),
+            DayPhase::Night => "Night is falling, stay close to a fire".to_string(),
+            DayPhase::Day | DayPhase::Dusk => continue,
+        };
+        for (ent, _) in announcement_query.iter() {
+            commands.entity(ent).despawn_recursive();
+        }
+        commands
+            .spawn_bundle(TextBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: Rect {
+                        top: Val::Px(20.0),
+                        left: Val::Percent(40.0),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                text: Text::with_section(
+                    message,
+                    TextStyle {
+                        font: asset_server.load("fonts/QuattrocentoSans-Regular.ttf"),
+                        font_size: 30.0,
+                        color: Color::WHITE,
+                    },
+                    Default::default(),
+                ),
+                ..Default::default()
+            })
+            .insert(PhaseAnnouncement {
+                timer: Timer::from_seconds(ANNOUNCEMENT_SECONDS, false),
+            })
+            .insert(Name::new("Phase Announcement"));
+    }
+}
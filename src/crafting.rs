@@ -1,71 +1,755 @@
-use bevy::{input::keyboard, prelude::*};
+use bevy::{prelude::*, utils::HashSet};
+use serde::Deserialize;
 
-use crate::{inventory::{Inventory, give_item, remove_item}, items::ItemType};
+use crate::{
+    chest::ChestMenu,
+    fire::Campfire,
+    graphics::PlaceHolderGraphics,
+    inventory::{count_item, give_or_drop, remove_item, Inventory, InventoryEvents, ItemPickedUp},
+    items::{spawn_object, ItemType, Object, WorldObject},
+    player::Player,
+    read_asset,
+    registry::ItemRegistry,
+    GameCamera,
+};
 
-#[derive(Clone)]
+pub const RECIPES_PATH: &str = "recipes.ron";
+/// How far the player can wander from where they started crafting before it is cancelled
+pub const CRAFTING_CANCEL_DISTANCE: f32 = 20.0;
+
+const PROGRESS_BAR_WIDTH: f32 = 60.0;
+const PROGRESS_BAR_HEIGHT: f32 = 8.0;
+
+const ROW_X: f32 = 420.0;
+const ROW_TOP: f32 = 300.0;
+const ROW_SPACING: f32 = 60.0;
+const ROW_WIDTH: f32 = 340.0;
+const ROW_HEIGHT: f32 = 50.0;
+
+const NOTIFICATION_SECONDS: f32 = 3.0;
+
+#[derive(Clone, Deserialize)]
 pub struct CraftingRecipe {
     needed: Vec<ItemAndCount>,
-    produces: ItemType,
+    produces: WorldObject,
+    #[serde(default)]
+    station: Option<WorldObject>,
+    /// Seconds it takes to craft, has to be above 0 so the progress bar has something to fill
+    duration: f32,
+    /// Crafting any of these teaches the recipe, recipes without
+    /// prerequisites are learned by picking up one of their ingredients
+    #[serde(default)]
+    prerequisites: Vec<WorldObject>,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Deserialize)]
 pub struct ItemAndCount {
     item: ItemType,
     count: usize,
 }
 
+#[derive(Deserialize)]
 pub struct CraftingBook {
     recipes: Vec<CraftingRecipe>,
 }
 
+#[derive(Default)]
+pub struct CraftingMenu {
+    open: bool,
+    selected: usize,
+    nearby_stations: HashSet<WorldObject>,
+}
+
+impl CraftingMenu {
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+}
+
+#[derive(Component)]
+pub struct CraftingMenuUi;
+
+/// Recipes the player has discovered, recipes are known by what they produce
+#[derive(Component, Default, Clone)]
+pub struct KnownRecipes {
+    pub known: HashSet<WorldObject>,
+}
+
+pub struct RecipeLearned {
+    pub learner: Entity,
+    pub produces: WorldObject,
+}
+
+#[derive(Component)]
+pub struct RecipeNotification {
+    timer: Timer,
+}
+
+/// A craft in progress, the ingredients are already taken out of the inventory
+#[derive(Component)]
+pub struct CraftingJob {
+    recipe: usize,
+    timer: Timer,
+    start: Vec2,
+    bar: Entity,
+    fill: Entity,
+}
+
+impl CraftingJob {
+    /// The progress bar shown over the crafter
+    pub fn bar(&self) -> Entity {
+        self.bar
+    }
+
+    /// What was taken out of the inventory to start it
+    pub fn ingredients<'a>(
+        &self,
+        book: &'a CraftingBook,
+    ) -> impl Iterator<Item = (ItemType, usize)> + 'a {
+        book.recipes[self.recipe]
+            .needed
+            .iter()
+            .map(|needed| (needed.item, needed.count))
+    }
+}
+
+/// Asks to start crafting a recipe, by its index in the book
+pub struct CraftRequested {
+    pub recipe: usize,
+}
+
+pub struct CraftCompleted {
+    pub crafter: Entity,
+    pub produces: WorldObject,
+}
+
+#[derive(Component)]
+pub struct CraftingRow {
+    recipe: usize,
+    row: usize,
+}
+
 pub struct CraftingPlugin;
 
 impl Plugin for CraftingPlugin {
     fn build(&self, app: &mut App) {
-        app.insert_resource(CraftingBook {
-            recipes: vec![CraftingRecipe {
-                needed: vec![
-                    ItemAndCount {
-                        item: ItemType::Twig,
-                        count: 1,
-                    },
-                    ItemAndCount {
-                        item: ItemType::Flint,
-                        count: 1,
-                    },
-                ],
-                produces: ItemType::Axe,
-            }],
-        })
-        .add_system(test_crafting);
+        app.insert_resource(load_crafting_book(RECIPES_PATH))
+            .init_resource::<CraftingMenu>()
+            .add_system(find_nearby_stations.before("craftinginput"))
+            .add_system(crafting_menu_input.label("craftinginput"))
+            .add_system(
+                tick_crafting_jobs
+                    .label("craftingjobs")
+                    .after("craftinginput")
+                    //Still sees the chest that the same Escape press is about to close
+                    .before("chestinput"),
+            )
+            .add_system(learn_recipes.after("craftinginput"))
+            .add_event::<CraftRequested>()
+            .add_event::<CraftCompleted>()
+            .add_event::<RecipeLearned>();
     }
 }
 
-fn can_craft(inventory: &mut Inventory, recipe: &CraftingRecipe) -> bool {
-    for item_and_count in recipe.needed.iter() {
-        let mut found_item = false;
-        for slot in inventory.items.iter() {
-            if slot.item.types == item_and_count.item && slot.count >= item_and_count.count {
-                found_item = true;
+/// The crafting menu and notifications, left out when running headless
+pub struct CraftingUiPlugin;
+
+impl Plugin for CraftingUiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(crafting_menu_click.before("craftinginput"))
+            .add_system(update_crafting_menu.after("craftinginput"))
+            .add_system(show_recipe_notifications);
+    }
+}
+
+/// Reads the recipe list from disk so recipes can be added without recompiling, it is read once
+/// at startup so an invalid file stops the game before the first frame
+pub fn load_crafting_book(path: &str) -> CraftingBook {
+    let contents = read_asset(path);
+    let book = parse_crafting_book(&contents)
+        .unwrap_or_else(|err| panic!("Invalid recipe file {}: {}", path, err));
+    info!("Loaded {} recipes from {}", book.recipes.len(), path);
+    book
+}
+
+pub fn parse_crafting_book(contents: &str) -> Result<CraftingBook, String> {
+    let book: CraftingBook = ron::de::from_str(contents).map_err(|err| err.to_string())?;
+    for (i, recipe) in book.recipes.iter().enumerate() {
+        if recipe.needed.is_empty() {
+            return Err(format!(
+                "recipe {} ({:?}) needs no items",
+                i, recipe.produces
+            ));
+        }
+        for needed in recipe.needed.iter() {
+            if matches!(needed.item, ItemType::None | ItemType::Default) {
+                return Err(format!(
+                    "recipe {} ({:?}) needs {:?}, which is not a real item",
+                    i, recipe.produces, needed.item
+                ));
             }
+            if needed.count == 0 {
+                return Err(format!(
+                    "recipe {} ({:?}) needs 0 of {:?}",
+                    i, recipe.produces, needed.item
+                ));
+            }
+        }
+        if !recipe.duration.is_finite() || recipe.duration <= 0.0 {
+            return Err(format!(
+                "recipe {} ({:?}) needs a duration above 0",
+                i, recipe.produces
+            ));
+        }
+        if recipe.prerequisites.contains(&recipe.produces) {
+            return Err(format!(
+                "recipe {} ({:?}) is its own prerequisite",
+                i, recipe.produces
+            ));
+        }
+        if let Some(WorldObject::Item(item)) = recipe.station {
+            return Err(format!(
+                "recipe {} ({:?}) uses {:?} as a station, stations must be world objects",
+                i, recipe.produces, item
+            ));
+        }
+    }
+    Ok(book)
+}
+
+impl KnownRecipes {
+    pub fn knows(&self, recipe: &CraftingRecipe) -> bool {
+        self.known.contains(&recipe.produces)
+    }
+}
+
+/// Indices of the recipes shown in the menu, in book order
+fn known_recipe_indices(book: &CraftingBook, known: &KnownRecipes) -> Vec<usize> {
+    (0..book.recipes.len())
+        .filter(|i| known.knows(&book.recipes[*i]))
+        .collect()
+}
+
+/// Whether the inventory holds every ingredient, counting across all slots
+pub fn can_craft(inventory: &Inventory, recipe: &CraftingRecipe) -> bool {
+    recipe
+        .needed
+        .iter()
+        .all(|needed| count_item(inventory, needed.item) >= needed.count)
+}
+
+/// Whether the recipe's station, if it needs one, is among the nearby objects
+pub fn has_station(recipe: &CraftingRecipe, nearby_stations: &HashSet<WorldObject>) -> bool {
+    match recipe.station {
+        Some(station) => nearby_stations.contains(&station),
+        None => true,
+    }
+}
+
+/// Takes the ingredients for a craft as a single transaction: nothing is taken
+/// unless all of them are present and there is room for the output afterwards
+pub fn reserve_ingredients(
+    owner: Entity,
+    inventory: &mut Inventory,
+    recipe: &CraftingRecipe,
+    registry: &ItemRegistry,
+    events: &mut InventoryEvents,
+) -> bool {
+    if !can_craft(inventory, recipe) {
+        return false;
+    }
+    let mut after = inventory.clone();
+    for needed in recipe.needed.iter() {
+        if !after.remove(needed.item, needed.count) {
+            return false;
         }
-        if !found_item {
+    }
+    //Only check there will be room, the output is given when the craft finishes
+    if let WorldObject::Item(item) = recipe.produces {
+        if after.add(item, 1, registry) > 0 {
             return false;
         }
     }
-    for item_and_count in recipe.needed.iter() {
-        remove_item(inventory, item_and_count.item, 1);
+    //All of them are there, so taking them for real can't fail and gets reported
+    for needed in recipe.needed.iter() {
+        remove_item(owner, inventory, needed.item, needed.count, events);
+    }
+    true
+}
+
+/// Stations count when they are within arm's reach, like fueling or harvesting them
+fn find_nearby_stations(
+    mut menu: ResMut<CraftingMenu>,
+    player_query: Query<(&Transform, &Player)>,
+    object_query: Query<(&Transform, &Object, Option<&Campfire>)>,
+) {
+    let (transform, player) = player_query.single();
+    let player_pos = transform.translation.truncate();
+    let nearby_stations: HashSet<WorldObject> = object_query
+        .iter()
+        .filter(|(transform, _, _)| {
+            Vec2::distance(transform.translation.truncate(), player_pos) < player.arm_length
+        })
+        //A campfire that burnt out can't cook anything
+        .filter(|(_, _, campfire)| campfire.is_none_or(|campfire| campfire.is_lit()))
+        .map(|(_, object, _)| object.object)
+        .collect();
+    //Only touch the menu when something changed so the UI isn't rebuilt every frame
+    if nearby_stations != menu.nearby_stations {
+        menu.nearby_stations = nearby_stations;
+    }
+}
+
+/// Clicking a row selects and crafts it
+fn crafting_menu_click(
+    mouse: Res<Input<MouseButton>>,
+    windows: Res<Windows>,
+    mut menu: ResMut<CraftingMenu>,
+    mut requests: EventWriter<CraftRequested>,
+    row_query: Query<(&CraftingRow, &Transform)>,
+) {
+    if !menu.open || !mouse.just_pressed(MouseButton::Left) {
+        return;
+    }
+    if let Some(cursor) = windows.get_primary().and_then(|window| {
+        window
+            .cursor_position()
+            .map(|pos| pos - Vec2::new(window.width(), window.height()) / 2.0)
+    }) {
+        for (row, transform) in row_query.iter() {
+            let min = transform.translation.truncate() - Vec2::splat(ROW_HEIGHT / 2.0);
+            let max = min + Vec2::new(ROW_WIDTH, ROW_HEIGHT);
+            if cursor.x >= min.x && cursor.x <= max.x && cursor.y >= min.y && cursor.y <= max.y {
+                menu.selected = row.row;
+                requests.send(CraftRequested { recipe: row.recipe });
+            }
+        }
     }
-    return true;
 }
 
-fn test_crafting(
-    mut inventory_query: Query<&mut Inventory>,
+fn crafting_menu_input(
+    mut commands: Commands,
+    keyboard: Res<Input<KeyCode>>,
+    registry: Res<ItemRegistry>,
     crafting_book: Res<CraftingBook>,
+    mut requests: EventReader<CraftRequested>,
+    mut inventory_events: InventoryEvents,
+    mut menu: ResMut<CraftingMenu>,
+    mut player_query: Query<
+        (
+            Entity,
+            &Transform,
+            &mut Inventory,
+            &KnownRecipes,
+            Option<&CraftingJob>,
+        ),
+        With<Player>,
+    >,
+) {
+    if keyboard.just_pressed(KeyCode::C) {
+        menu.open = !menu.open;
+    }
+    let (player_ent, player_transform, mut inventory, known, job) = player_query.single_mut();
+    let visible = known_recipe_indices(&crafting_book, known);
+    if !menu.open || visible.is_empty() {
+        return;
+    }
+    let row_count = visible.len();
+    if menu.selected >= row_count {
+        menu.selected = row_count - 1;
+    }
+    if keyboard.just_pressed(KeyCode::Up) {
+        menu.selected = (menu.selected + row_count - 1) % row_count;
+    }
+    if keyboard.just_pressed(KeyCode::Down) {
+        menu.selected = (menu.selected + 1) % row_count;
+    }
+
+    let mut to_craft = None;
+    if keyboard.just_pressed(KeyCode::Return) {
+        to_craft = Some(visible[menu.selected]);
+    }
+    for request in requests.iter() {
+        to_craft = Some(request.recipe);
+    }
+
+    if let Some(index) = to_craft {
+        let recipe = &crafting_book.recipes[index];
+        if job.is_some()
+            || !has_station(recipe, &menu.nearby_stations)
+            || !reserve_ingredients(
+                player_ent,
+                &mut inventory,
+                recipe,
+                &registry,
+                &mut inventory_events,
+            )
+        {
+            return;
+        }
+        let (bar, fill) = spawn_progress_bar(&mut commands);
+        commands
+            .entity(player_ent)
+            .insert(CraftingJob {
+                recipe: index,
+                timer: Timer::from_seconds(recipe.duration, false),
+                start: player_transform.translation.truncate(),
+                bar: bar,
+                fill: fill,
+            })
+            .add_child(bar);
+    }
+}
+
+fn spawn_progress_bar(commands: &mut Commands) -> (Entity, Entity) {
+    let fill = commands
+        .spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color: Color::rgb(0.9, 0.7, 0.2),
+                custom_size: Some(Vec2::new(0.0, PROGRESS_BAR_HEIGHT)),
+                ..Default::default()
+            },
+            transform: Transform::from_xyz(0.0, 0.0, 0.1),
+            ..Default::default()
+        })
+        .id();
+    let bar = commands
+        .spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color: Color::rgba(0.0, 0.0, 0.0, 0.6),
+                custom_size: Some(Vec2::new(PROGRESS_BAR_WIDTH, PROGRESS_BAR_HEIGHT)),
+                ..Default::default()
+            },
+            transform: Transform::from_xyz(0.0, 60.0, 1.0),
+            ..Default::default()
+        })
+        .insert(Name::new("Crafting Progress"))
+        .add_child(fill)
+        .id();
+    (bar, fill)
+}
+
+/// Runs the timers of running crafts, walking away or Escape cancels them and refunds the
+/// ingredients. Escape is left to an open chest, which it closes instead
+fn tick_crafting_jobs(
+    mut commands: Commands,
+    time: Res<Time>,
     keyboard: Res<Input<KeyCode>>,
+    chest_menu: Res<ChestMenu>,
+    graphics: Res<PlaceHolderGraphics>,
+    registry: Res<ItemRegistry>,
+    crafting_book: Res<CraftingBook>,
+    mut completed_events: EventWriter<CraftCompleted>,
+    mut inventory_events: InventoryEvents,
+    mut player_query: Query<(Entity, &Transform, &mut Inventory, &mut CraftingJob)>,
+    mut fill_query: Query<(&mut Sprite, &mut Transform), Without<CraftingJob>>,
 ) {
-    let mut inventory = inventory_query.single_mut();
-    if keyboard.just_pressed(KeyCode::F) && can_craft(&mut inventory, &crafting_book.recipes[0]) {
-        give_item(&mut inventory, crafting_book.recipes[0].produces);
+    for (ent, transform, mut inventory, mut job) in player_query.iter_mut() {
+        let recipe = &crafting_book.recipes[job.recipe];
+        let position = transform.translation.truncate();
+
+        let moved_away = Vec2::distance(position, job.start) > CRAFTING_CANCEL_DISTANCE;
+        let escape = keyboard.just_pressed(KeyCode::Escape) && chest_menu.chest.is_none();
+        if moved_away || escape {
+            for needed in recipe.needed.iter() {
+                give_or_drop(
+                    ent,
+                    &mut inventory,
+                    needed.item,
+                    needed.count,
+                    position,
+                    &mut commands,
+                    &graphics,
+                    &registry,
+                    &mut inventory_events,
+                );
+            }
+            commands.entity(job.bar).despawn_recursive();
+            commands.entity(ent).remove::<CraftingJob>();
+            continue;
+        }
+
+        job.timer.tick(time.delta());
+        if let Ok((mut sprite, mut fill_transform)) = fill_query.get_mut(job.fill) {
+            let width = PROGRESS_BAR_WIDTH * job.timer.percent();
+            sprite.custom_size = Some(Vec2::new(width, PROGRESS_BAR_HEIGHT));
+            fill_transform.translation.x = (width - PROGRESS_BAR_WIDTH) / 2.0;
+        }
+        if !job.timer.finished() {
+            continue;
+        }
+
+        match recipe.produces {
+            WorldObject::Item(item) => give_or_drop(
+                ent,
+                &mut inventory,
+                item,
+                1,
+                position,
+                &mut commands,
+                &graphics,
+                &registry,
+                &mut inventory_events,
+            ),
+            //Placed just below the player so it doesn't hide behind them
+            other => {
+                spawn_object(
+                    other,
+                    position - Vec2::new(0.0, 50.0),
+                    &mut commands,
+                    &graphics,
+                );
+            }
+        }
+        completed_events.send(CraftCompleted {
+            crafter: ent,
+            produces: recipe.produces,
+        });
+        commands.entity(job.bar).despawn_recursive();
+        commands.entity(ent).remove::<CraftingJob>();
+    }
+}
+
+fn update_crafting_menu(
+    mut commands: Commands,
+    menu: Res<CraftingMenu>,
+    crafting_book: Res<CraftingBook>,
+    graphics: Res<PlaceHolderGraphics>,
+    registry: Res<ItemRegistry>,
+    asset_server: Res<AssetServer>,
+    player_query: Query<
+        (
+            &Inventory,
+            ChangeTrackers<Inventory>,
+            &KnownRecipes,
+            ChangeTrackers<KnownRecipes>,
+        ),
+        With<Player>,
+    >,
+    camera_query: Query<Entity, With<GameCamera>>,
+    ui_query: Query<Entity, With<CraftingMenuUi>>,
+) {
+    let (inventory, inventory_tracker, known, known_tracker) = player_query.single();
+    if !menu.is_changed() && !inventory_tracker.is_changed() && !known_tracker.is_changed() {
+        return;
+    }
+    for ent in ui_query.iter() {
+        commands.entity(ent).despawn_recursive();
+    }
+    if !menu.open {
+        return;
+    }
+
+    let font = asset_server.load("fonts/QuattrocentoSans-Regular.ttf");
+    let mut rows = Vec::new();
+    rows.push(
+        commands
+            .spawn_bundle(Text2dBundle {
+                text: Text::with_section(
+                    "Crafting",
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 30.0,
+                        color: Color::BLACK,
+                    },
+                    TextAlignment {
+                        vertical: VerticalAlign::Center,
+                        horizontal: HorizontalAlign::Left,
+                    },
+                ),
+                transform: Transform::from_xyz(
+                    ROW_X - ROW_HEIGHT / 2.0,
+                    ROW_TOP + ROW_SPACING,
+                    -1.0,
+                ),
+                ..Default::default()
+            })
+            .id(),
+    );
+
+    for (i, recipe_index) in known_recipe_indices(&crafting_book, known)
+        .into_iter()
+        .enumerate()
+    {
+        let recipe = &crafting_book.recipes[recipe_index];
+        let craftable = can_craft(inventory, recipe) && has_station(recipe, &menu.nearby_stations);
+        let tint = if craftable {
+            Color::WHITE
+        } else {
+            Color::rgba(1.0, 1.0, 1.0, 0.35)
+        };
+        let text_color = if craftable { Color::BLACK } else { Color::GRAY };
+
+        let mut box_sprite = TextureAtlasSprite::new(if i == menu.selected {
+            graphics.selected_box_index
+        } else {
+            graphics.box_index
+        });
+        box_sprite.custom_size = Some(Vec2::splat(ROW_HEIGHT));
+        box_sprite.color = tint;
+
+        let mut icon = TextureAtlasSprite::new(graphics.object_index(recipe.produces));
+        icon.custom_size = Some(Vec2::splat(25.0));
+        icon.color = tint * graphics.object_color(recipe.produces).as_rgba_f32();
+
+        let ingredients = recipe
+            .needed
+            .iter()
+            .map(|needed| {
+                format!(
+                    "{} {}/{}",
+                    registry.name(needed.item),
+                    count_item(inventory, needed.item),
+                    needed.count
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        let label_text = match recipe.station {
+            Some(station) => format!("{} (at {:?})", ingredients, station),
+            None => ingredients,
+        };
+
+        let icon = commands
+            .spawn_bundle(SpriteSheetBundle {
+                sprite: icon,
+                texture_atlas: graphics.texture_atlas.clone(),
+                transform: Transform::from_xyz(0.0, 0.0, 0.1),
+                ..Default::default()
+            })
+            .id();
+        let label = commands
+            .spawn_bundle(Text2dBundle {
+                text: Text::with_section(
+                    label_text,
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 20.0,
+                        color: text_color,
+                    },
+                    TextAlignment {
+                        vertical: VerticalAlign::Center,
+                        horizontal: HorizontalAlign::Left,
+                    },
+                ),
+                transform: Transform::from_xyz(ROW_HEIGHT / 2.0 + 10.0, 0.0, 0.1),
+                ..Default::default()
+            })
+            .id();
+        rows.push(
+            commands
+                .spawn_bundle(SpriteSheetBundle {
+                    sprite: box_sprite,
+                    texture_atlas: graphics.texture_atlas.clone(),
+                    transform: Transform::from_xyz(ROW_X, ROW_TOP - ROW_SPACING * i as f32, -1.0),
+                    ..Default::default()
+                })
+                .insert(CraftingRow {
+                    recipe: recipe_index,
+                    row: i,
+                })
+                .insert(Name::new("Crafting Row"))
+                .push_children(&[icon, label])
+                .id(),
+        );
+    }
+
+    let menu_ent = commands
+        .spawn_bundle((Transform::default(), GlobalTransform::default()))
+        .insert(CraftingMenuUi)
+        .insert(Name::new("Crafting Menu"))
+        .push_children(&rows)
+        .id();
+    commands.entity(camera_query.single()).add_child(menu_ent);
+}
+
+fn learn_recipes(
+    crafting_book: Res<CraftingBook>,
+    mut pickup_events: EventReader<ItemPickedUp>,
+    mut completed_events: EventReader<CraftCompleted>,
+    mut learned_events: EventWriter<RecipeLearned>,
+    mut known_query: Query<&mut KnownRecipes>,
+) {
+    let mut learn = |learner: Entity, teaches: &dyn Fn(&CraftingRecipe) -> bool| {
+        if let Ok(mut known) = known_query.get_mut(learner) {
+            for recipe in crafting_book.recipes.iter() {
+                if !known.knows(recipe) && teaches(recipe) {
+                    known.known.insert(recipe.produces);
+                    learned_events.send(RecipeLearned {
+                        learner: learner,
+                        produces: recipe.produces,
+                    });
+                }
+            }
+        }
+    };
+    for pickup in pickup_events.iter() {
+        learn(pickup.picker, &|recipe| {
+            recipe.prerequisites.is_empty()
+                && recipe
+                    .needed
+                    .iter()
+                    .any(|needed| needed.item == pickup.item)
+        });
+    }
+    for completed in completed_events.iter() {
+        learn(completed.crafter, &|recipe| {
+            recipe.prerequisites.contains(&completed.produces)
+        });
+    }
+}
+
+fn show_recipe_notifications(
+    mut commands: Commands,
+    time: Res<Time>,
+    asset_server: Res<AssetServer>,
+    registry: Res<ItemRegistry>,
+    mut learned_events: EventReader<RecipeLearned>,
+    mut notification_query: Query<(Entity, &mut RecipeNotification)>,
+    player_query: Query<(), With<Player>>,
+) {
+    for (ent, mut notification) in notification_query.iter_mut() {
+        notification.timer.tick(time.delta());
+        if notification.timer.finished() {
+            commands.entity(ent).despawn_recursive();
+        }
+    }
+
+    let shown = notification_query.iter().count();
+    for (i, learned) in learned_events
+        .iter()
+        .filter(|learned| player_query.get(learned.learner).is_ok())
+        .enumerate()
+    {
+        let name = match learned.produces {
+            WorldObject::Item(item) => registry.name(item),
+            other => format!("{:?}", other),
+        };
+        commands
+            .spawn_bundle(TextBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: Rect {
+                        top: Val::Px(20.0 + 30.0 * (shown + i) as f32),
+                        left: Val::Px(20.0),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                text: Text::with_section(
+                    format!("Learned a new recipe: {}", name),
+                    TextStyle {
+                        font: asset_server.load("fonts/QuattrocentoSans-Regular.ttf"),
+                        font_size: 25.0,
+                        color: Color::BLACK,
+                    },
+                    Default::default(),
+                ),
+                ..Default::default()
+            })
+            .insert(RecipeNotification {
+                timer: Timer::from_seconds(NOTIFICATION_SECONDS, false),
+            })
+            .insert(Name::new("Recipe Notification"));
     }
 }
@@ -0,0 +1,327 @@
+use bevy::{
+    ecs::system::{lifetimeless::SRes, SystemParamItem},
+    prelude::*,
+    reflect::TypeUuid,
+    render::{
+        render_asset::{PrepareAssetError, RenderAsset},
+        render_resource::{
+            std140::{AsStd140, Std140},
+            *,
+        },
+        renderer::RenderDevice,
+    },
+    sprite::{Material2d, Material2dPipeline, Material2dPlugin, MaterialMesh2dBundle},
+};
+use bevy_inspector_egui::{Inspectable, RegisterInspectable};
+
+use crate::{
+    clock::WorldClock,
+    harvest::{nearest_interactable, HarvestTable},
+    inventory::{remove_item, Inventory, InventoryEvents},
+    items::{Object, WorldObject},
+    player::{Player, INTERACT_KEY},
+    registry::ItemRegistry,
+    GameCamera, HEIGHT, RESOLUTION,
+};
+
+pub const FIRE_SHADER: &str = "fire_overlay.wgsl";
+/// Has to match MAX_FIRES in the shader
+pub const MAX_FIRES: usize = 64;
+
+/// Seconds of burning a freshly built campfire starts with
+const STARTING_FUEL: f32 = 60.0;
+const MAX_FUEL: f32 = 300.0;
+/// Light radius in pixels of a fire about to go out and of a fully fuelled one
+const MIN_LIGHT_RADIUS: f32 = 60.0;
+const MAX_LIGHT_RADIUS: f32 = 220.0;
+/// Fuel burns in steps this many seconds apart, the light shrinks by about half a pixel each
+const BURN_STEP: f32 = 1.0;
+
+#[derive(Component, Inspectable)]
+pub struct Campfire {
+    /// Seconds left before it goes out
+    pub fuel: f32,
+}
+
+impl Campfire {
+    pub fn is_lit(&self) -> bool {
+        self.fuel > 0.0
+    }
+
+    /// Shrinks as the fuel runs out, 0 once the fire is out
+    pub fn light_radius(&self) -> f32 {
+        if !self.is_lit() {
+            return 0.0;
+        }
+        MIN_LIGHT_RADIUS + (MAX_LIGHT_RADIUS - MIN_LIGHT_RADIUS) * (self.fuel / MAX_FUEL).min(1.0)
+    }
+}
+
+#[derive(Clone, Copy, Default, AsStd140)]
+pub struct FireLight {
+    pub position: Vec2,
+    pub strength: f32,
+}
+
+impl FireLight {
+    /// Unused slots sit far away so they never light anything
+    fn unused() -> Self {
+        FireLight {
+            position: Vec2::splat(1.0e6),
+            strength: 1.0,
+        }
+    }
+}
+
+/// Darkens the screen everywhere except around lit fires
+#[derive(Clone, TypeUuid)]
+#[uuid = "6b0a6a29-0c5e-4f0e-9d43-4f1f4d3b8a17"]
+pub struct FireOverlayMaterial {
+    pub fires: [FireLight; MAX_FIRES],
+    /// Alpha of the overlay away from any fire
+    pub darkness: f32,
+}
+
+#[derive(Clone, AsStd140)]
+struct FiresUniform {
+    fires: [FireLight; MAX_FIRES],
+    darkness: f32,
+}
+
+pub struct GpuFireOverlayMaterial {
+    _buffer: Buffer,
+    bind_group: BindGroup,
+}
+
+impl RenderAsset for FireOverlayMaterial {
+    type ExtractedAsset = FireOverlayMaterial;
+    type PreparedAsset = GpuFireOverlayMaterial;
+    type Param = (SRes<RenderDevice>, SRes<Material2dPipeline<Self>>);
+
+    fn extract_asset(&self) -> Self::ExtractedAsset {
+        self.clone()
+    }
+
+    fn prepare_asset(
+        material: Self::ExtractedAsset,
+        (render_device, pipeline): &mut SystemParamItem<Self::Param>,
+    ) -> Result<Self::PreparedAsset, PrepareAssetError<Self::ExtractedAsset>> {
+        let uniform = FiresUniform {
+            fires: material.fires,
+            darkness: material.darkness,
+        };
+        let buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+            label: Some("fire_overlay_uniform_buffer"),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            contents: uniform.as_std140().as_bytes(),
+        });
+        let bind_group = render_device.create_bind_group(&BindGroupDescriptor {
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+            label: Some("fire_overlay_bind_group"),
+            layout: &pipeline.material2d_layout,
+        });
+        Ok(GpuFireOverlayMaterial {
+            _buffer: buffer,
+            bind_group: bind_group,
+        })
+    }
+}
+
+impl Material2d for FireOverlayMaterial {
+    fn fragment_shader(asset_server: &AssetServer) -> Option<Handle<Shader>> {
+        Some(asset_server.load(FIRE_SHADER))
+    }
+
+    fn bind_group(render_asset: &<Self as RenderAsset>::PreparedAsset) -> &BindGroup {
+        &render_asset.bind_group
+    }
+
+    fn bind_group_layout(render_device: &RenderDevice) -> BindGroupLayout {
+        render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: BufferSize::new(FiresUniform::std140_size_static() as u64),
+                },
+                count: None,
+            }],
+            label: Some("fire_overlay_layout"),
+        })
+    }
+}
+
+/// Marks the screen sized quad drawing the darkness
+#[derive(Component)]
+pub struct FireOverlay;
+
+pub struct FirePlugin;
+
+impl Plugin for FirePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(light_new_campfires)
+            .add_system(burn_fuel)
+            .add_system(fuel_campfires)
+            .register_inspectable::<Campfire>();
+    }
+}
+
+/// Draws the darkness and firelight, needs the renderer
+pub struct FireOverlayPlugin;
+
+impl Plugin for FireOverlayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(Material2dPlugin::<FireOverlayMaterial>::default())
+            .add_startup_system(spawn_fire_overlay)
+            //Runs after the update stage so campfires despawned during it are seen
+            .add_system_to_stage(CoreStage::PostUpdate, update_fire_overlay);
+    }
+}
+
+fn spawn_fire_overlay(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<FireOverlayMaterial>>,
+    camera_query: Query<Entity, With<GameCamera>>,
+) {
+    let camera_ent = camera_query.single();
+    let overlay = commands
+        .spawn_bundle(MaterialMesh2dBundle {
+            mesh: meshes
+                .add(Mesh::from(shape::Quad::new(Vec2::new(
+                    HEIGHT * RESOLUTION,
+                    HEIGHT,
+                ))))
+                .into(),
+            material: materials.add(FireOverlayMaterial {
+                fires: [FireLight::unused(); MAX_FIRES],
+                darkness: 0.0,
+            }),
+            //Above the world but below the hotbar and crafting menu
+            transform: Transform::from_xyz(0.0, 0.0, -2.0),
+            ..Default::default()
+        })
+        .insert(FireOverlay)
+        .insert(Name::new("FireOverlay"))
+        .id();
+    commands.entity(camera_ent).add_child(overlay);
+}
+
+/// Placed campfires start burning with a bit of fuel
+fn light_new_campfires(
+    mut commands: Commands,
+    object_query: Query<(Entity, &Object), (Changed<Object>, Without<Campfire>)>,
+) {
+    for (ent, object) in object_query.iter() {
+        if object.object == WorldObject::CampFire {
+            commands.entity(ent).insert(Campfire {
+                fuel: STARTING_FUEL,
+            });
+        }
+    }
+}
+
+/// Burns in steps so campfires, and the overlay watching for changed ones, don't change every frame
+fn burn_fuel(time: Res<Time>, mut unburnt: Local<f32>, mut campfire_query: Query<&mut Campfire>) {
+    *unburnt += time.delta_seconds();
+    if *unburnt < BURN_STEP {
+        return;
+    }
+    for mut campfire in campfire_query.iter_mut() {
+        if campfire.is_lit() {
+            campfire.fuel = (campfire.fuel - *unburnt).max(0.0);
+        }
+    }
+    *unburnt = 0.0;
+}
+
+/// Interacting with the closest campfire while holding something that burns puts it in
+fn fuel_campfires(
+    keyboard: Res<Input<KeyCode>>,
+    registry: Res<ItemRegistry>,
+    table: Res<HarvestTable>,
+    mut inventory_events: InventoryEvents,
+    mut player_query: Query<(Entity, &Transform, &Player, &mut Inventory)>,
+    mut object_query: Query<(Entity, &Transform, &Object, Option<&mut Campfire>), Without<Player>>,
+) {
+    if !keyboard.just_pressed(INTERACT_KEY) {
+        return;
+    }
+    let (player_ent, player_transform, player, mut inventory) = player_query.single_mut();
+    let player_pos = player_transform.translation.truncate();
+
+    let objects = object_query
+        .iter_mut()
+        .map(|(ent, transform, object, _)| (ent, transform.translation.truncate(), object.object));
+    let nearest = nearest_interactable(&table, player, player_pos, objects);
+
+    if let Some((_, _, _, Some(mut campfire))) =
+        nearest.and_then(|ent| object_query.get_mut(ent).ok())
+    {
+        let item = inventory.selected_item();
+        let fuel = registry.fuel(item);
+        if fuel <= 0.0 {
+            info!("{} doesn't burn", registry.name(item));
+            return;
+        }
+        if campfire.fuel >= MAX_FUEL {
+            info!("The fire is already full");
+            return;
+        }
+        if remove_item(player_ent, &mut inventory, item, 1, &mut inventory_events) {
+            campfire.fuel = (campfire.fuel + fuel).min(MAX_FUEL);
+        }
+    }
+}
+
+/// Sends the closest lit fires and how dark it is to the overlay shader, the material
+/// is only written when a campfire or the darkness changed
+fn update_fire_overlay(
+    clock: Res<WorldClock>,
+    mut materials: ResMut<Assets<FireOverlayMaterial>>,
+    overlay_query: Query<(&Handle<FireOverlayMaterial>, &GlobalTransform), With<FireOverlay>>,
+    campfire_query: Query<(&Transform, &Campfire)>,
+    changed_query: Query<(), Changed<Campfire>>,
+    removed: RemovedComponents<Campfire>,
+) {
+    let (handle, overlay_transform) = overlay_query.single();
+    let darkness = clock.darkness();
+    let fires_changed = !changed_query.is_empty() || removed.iter().next().is_some();
+    let darkness_changed = materials
+        .get(handle)
+        .is_some_and(|material| material.darkness != darkness);
+    if !fires_changed && !darkness_changed {
+        return;
+    }
+    let center = overlay_transform.translation.truncate();
+
+    let mut lit: Vec<(Vec2, f32)> = campfire_query
+        .iter()
+        .filter(|(_, campfire)| campfire.is_lit())
+        .map(|(transform, campfire)| (transform.translation.truncate(), campfire.light_radius()))
+        .collect();
+    lit.sort_by(|(a, _), (b, _)| {
+        a.distance_squared(center)
+            .partial_cmp(&b.distance_squared(center))
+            .unwrap()
+    });
+
+    if let Some(material) = materials.get_mut(handle) {
+        material.darkness = darkness;
+        for (i, fire) in material.fires.iter_mut().enumerate() {
+            *fire = match lit.get(i) {
+                //The shader compares squared distances scaled by 4
+                Some((position, radius)) => FireLight {
+                    position: *position,
+                    strength: 4.0 * radius * radius,
+                },
+                None => FireLight::unused(),
+            };
+        }
+    }
+}
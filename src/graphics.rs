@@ -1,25 +1,114 @@
+use std::collections::HashMap as StdHashMap;
+
 use bevy::{prelude::*, utils::HashMap};
+use serde::Deserialize;
+
+use crate::{
+    items::{ItemType, WorldObject},
+    read_asset,
+    registry::ItemRegistry,
+};
+
+pub const SPRITES_PATH: &str = "sprites_desc.ron";
+pub const SPRITE_SHEET: &str = "bevy_survival_sprites.png";
+/// World objects are drawn at this many screen pixels per sheet pixel
+pub const OBJECT_SCALE: f32 = 2.0;
+
+/// A sprite in the sheet, the anchor is the point in the sprite, counted from
+/// its top left corner, that sits on the object's position in the world.
+/// The tint lets one picture be reused for variants like cooked food
+#[derive(Clone, Copy, Deserialize)]
+pub struct MyRect {
+    pos: (f32, f32),
+    size: (f32, f32),
+    anchor: Option<(f32, f32)>,
+    #[serde(default)]
+    tint: Option<(f32, f32, f32)>,
+}
 
-use crate::items::ItemType;
+#[derive(Deserialize)]
+pub struct GraphicsDesc {
+    player: MyRect,
+    inventory_box: MyRect,
+    selected_box: MyRect,
+    map: StdHashMap<WorldObject, MyRect>,
+}
+
+#[derive(Clone, Copy)]
+pub struct ObjectGraphic {
+    pub index: usize,
+    pub size: Vec2,
+    /// Where the sprite's center sits relative to the object's position, in sheet pixels
+    pub anchor_offset: Vec2,
+    pub color: Color,
+}
 
 pub struct PlaceHolderGraphics {
     pub texture_atlas: Handle<TextureAtlas>,
     pub player_index: usize,
-    pub flint_index: usize,
     pub box_index: usize,
-    pub item_map: HashMap<ItemType, usize>,
-    pub axe_index: usize,
-    pub pinecone_index: usize,
-    pub twig_index: usize,
-    pub wood_index: usize,
-    pub fire_index: usize,
-    pub chopped_pinecone_index: usize,
-    pub none_index: usize,
+    pub object_map: HashMap<WorldObject, ObjectGraphic>,
     pub selected_box_index: usize,
 
     pub default_index: usize,
 }
 
+impl PlaceHolderGraphics {
+    /// Stand-in for running without a window, every sprite points at an empty atlas
+    pub fn headless() -> Self {
+        PlaceHolderGraphics {
+            texture_atlas: Handle::default(),
+            player_index: 0,
+            box_index: 0,
+            object_map: HashMap::default(),
+            selected_box_index: 0,
+
+            default_index: 0,
+        }
+    }
+
+    pub fn item_index(&self, item: ItemType) -> usize {
+        self.object_index(WorldObject::Item(item))
+    }
+
+    /// Sprite used to show an item lying in the world or in the inventory
+    pub fn item_sprite(&self, item: ItemType) -> TextureAtlasSprite {
+        let mut sprite = TextureAtlasSprite::new(self.item_index(item));
+        sprite.custom_size = Some(Vec2::splat(25.0));
+        sprite.color = self.object_color(WorldObject::Item(item));
+        sprite
+    }
+
+    pub fn object_index(&self, object: WorldObject) -> usize {
+        self.object_map
+            .get(&object)
+            .map_or(self.default_index, |graphic| graphic.index)
+    }
+
+    pub fn object_color(&self, object: WorldObject) -> Color {
+        self.object_map
+            .get(&object)
+            .map_or(Color::WHITE, |graphic| graphic.color)
+    }
+
+    /// Sprite for a placed object along with the offset that puts its anchor on the object's position
+    pub fn object_sprite(&self, object: WorldObject) -> (TextureAtlasSprite, Vec3) {
+        match self.object_map.get(&object) {
+            Some(graphic) => {
+                let mut sprite = TextureAtlasSprite::new(graphic.index);
+                sprite.custom_size = Some(graphic.size * OBJECT_SCALE);
+                sprite.color = graphic.color;
+                (sprite, (graphic.anchor_offset * OBJECT_SCALE).extend(0.0))
+            }
+            None => {
+                let mut sprite = TextureAtlasSprite::new(self.default_index);
+                sprite.custom_size = Some(Vec2::splat(16.0 * OBJECT_SCALE));
+                (sprite, Vec3::ZERO)
+            }
+        }
+    }
+}
+
 pub struct GraphicsPlugin;
 
 impl Plugin for GraphicsPlugin {
@@ -28,105 +117,80 @@ impl Plugin for GraphicsPlugin {
     }
 }
 
+pub fn load_graphics_desc(path: &str) -> GraphicsDesc {
+    let contents = read_asset(path);
+    ron::de::from_str(&contents)
+        .unwrap_or_else(|err| panic!("Invalid sprite file {}: {}", path, err))
+}
+
+fn add_rect(atlas: &mut TextureAtlas, rect: &MyRect) -> ObjectGraphic {
+    let min = Vec2::new(rect.pos.0, rect.pos.1);
+    let size = Vec2::new(rect.size.0, rect.size.1);
+    let index = atlas.add_texture(bevy::sprite::Rect {
+        min: min,
+        max: min + size,
+    });
+    //The sheet's y axis points down while the world's points up
+    let anchor_offset = match rect.anchor {
+        Some((x, y)) => Vec2::new(size.x / 2.0 - x, y - size.y / 2.0),
+        None => Vec2::ZERO,
+    };
+    let color = match rect.tint {
+        Some((r, g, b)) => Color::rgb(r, g, b),
+        None => Color::WHITE,
+    };
+    ObjectGraphic {
+        index: index,
+        size: size,
+        anchor_offset: anchor_offset,
+        color: color,
+    }
+}
+
 fn load_graphics(
     mut commands: Commands,
     assets: Res<AssetServer>,
+    registry: Res<ItemRegistry>,
     mut texture_assets: ResMut<Assets<TextureAtlas>>,
 ) {
-    let image_handle = assets.load("placeholder.png");
-    let mut atlas = TextureAtlas::new_empty(image_handle, Vec2::splat(256.0));
-    let player_index = atlas.add_texture(bevy::sprite::Rect {
-        min: Vec2::splat(0.0),
-        max: Vec2::splat(32.0),
-    });
-
-    let flint_index = atlas.add_texture(bevy::sprite::Rect {
-        min: Vec2::new(32.0, 0.0),
-        max: Vec2::new(48.0, 16.0),
-    });
-
-    let axe_index = atlas.add_texture(bevy::sprite::Rect {
-        min: Vec2::new(32.0, 18.0),
-        max: Vec2::new(48.0, 32.0),
-    });
-
-    let box_index = atlas.add_texture(bevy::sprite::Rect {
-        min: Vec2::new(0.0, 32.0),
-        max: Vec2::new(32.0, 64.0),
-    });
-
-    let grass_index = atlas.add_texture(bevy::sprite::Rect {
-        min: Vec2::new(50.0, 0.0),
-        max: Vec2::new(64.0, 16.0),
-    });
-
-    let pinecone_index = atlas.add_texture(bevy::sprite::Rect {
-        min: Vec2::new(0.0, 75.0),
-        max: Vec2::new(32.0, 112.0),
-    });
-
-    let twig_index = atlas.add_texture(bevy::sprite::Rect {
-        min: Vec2::new(49.0, 18.0),
-        max: Vec2::new(65.0, 32.0),
-    });
-
-    let wood_index = atlas.add_texture(bevy::sprite::Rect {
-        min: Vec2::new(34.0, 34.0),
-        max: Vec2::new(50.0, 51.0),
-    });
-
-    let fire_index = atlas.add_texture(bevy::sprite::Rect {
-        min: Vec2::new(32.0, 50.0),
-        max: Vec2::new(64.0, 95.0),
-    });
-
-    let chopped_pinecone_index = atlas.add_texture(bevy::sprite::Rect {
-        min: Vec2::new(0.0, 128.0),
-        max: Vec2::new(32.0, 128.0 + 16.0),
-    });
-
-    let none_index = atlas.add_texture(bevy::sprite::Rect {
-        min: Vec2::new(240.0 - 16.0, 240.0 - 16.0),
-        max: Vec2::new(256.0 - 16.0, 256.0 - 16.0),
-    });
-
-    let default_index = atlas.add_texture(bevy::sprite::Rect {
-        min: Vec2::new(240.0, 240.0),
-        max: Vec2::new(256.0, 256.0),
-    });
-
-    let selected_box_index = atlas.add_texture(bevy::sprite::Rect {
-        min: Vec2::new(65.0, 34.0),
-        max: Vec2::new(96.0, 64.0),
-    });
+    let desc = load_graphics_desc(SPRITES_PATH);
+
+    let mut missing: Vec<String> = registry
+        .iter()
+        .filter(|item| !desc.map.contains_key(&WorldObject::Item(item.id)))
+        .map(|item| format!("{:?}", item.id))
+        .collect();
+    if !missing.is_empty() {
+        missing.sort();
+        panic!(
+            "{} has no graphic for these items: {}",
+            SPRITES_PATH,
+            missing.join(", ")
+        );
+    }
 
-    let mut item_map = HashMap::default();
+    let image_handle = assets.load(SPRITE_SHEET);
+    let mut atlas = TextureAtlas::new_empty(image_handle, Vec2::splat(256.0));
+    let player_index = add_rect(&mut atlas, &desc.player).index;
+    let box_index = add_rect(&mut atlas, &desc.inventory_box).index;
+    let selected_box_index = add_rect(&mut atlas, &desc.selected_box).index;
 
-    item_map.insert(ItemType::Flint, flint_index);
-    item_map.insert(ItemType::Axe, axe_index);
-    item_map.insert(ItemType::Grass, grass_index);
-    item_map.insert(ItemType::PineCone, pinecone_index);
-    item_map.insert(ItemType::Twig, twig_index);
-    item_map.insert(ItemType::Wood, wood_index);
-    item_map.insert(ItemType::Fire, fire_index);
-    item_map.insert(ItemType::ChoppedPineCone, chopped_pinecone_index);
-    item_map.insert(ItemType::None, none_index);
+    let mut object_map = HashMap::default();
+    for (object, rect) in desc.map.iter() {
+        object_map.insert(*object, add_rect(&mut atlas, rect));
+    }
+    let default_index = object_map
+        .get(&WorldObject::Item(ItemType::None))
+        .unwrap_or_else(|| panic!("{} has no graphic for Item(None)", SPRITES_PATH))
+        .index;
 
     let atlas_handle = texture_assets.add(atlas);
 
     commands.insert_resource(PlaceHolderGraphics {
         texture_atlas: atlas_handle,
         player_index: player_index,
-        flint_index: flint_index,
         box_index: box_index,
-        item_map: item_map,
-        axe_index: axe_index,
-        pinecone_index: pinecone_index,
-        twig_index: twig_index,
-        wood_index: wood_index,
-        fire_index: fire_index,
-        chopped_pinecone_index: chopped_pinecone_index,
-        none_index: none_index,
+        object_map: object_map,
         selected_box_index: selected_box_index,
 
         default_index: default_index,
@@ -0,0 +1,244 @@
+use std::collections::HashMap as StdHashMap;
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    graphics::PlaceHolderGraphics,
+    inventory::{give_or_drop, wear_selected_tool, Inventory, InventoryEvents, ItemPickedUp},
+    items::{ItemType, Object, Tool, WorldObject},
+    player::{Player, INTERACT_KEY},
+    read_asset,
+    registry::ItemRegistry,
+};
+
+pub const HARVEST_PATH: &str = "harvest.ron";
+
+#[derive(Clone, Copy, Deserialize)]
+pub struct Harvest {
+    pub yields: ItemType,
+    pub count: usize,
+    /// Tool that has to be in the selected slot
+    #[serde(default)]
+    pub tool: Option<Tool>,
+    /// The depleted object left behind, None removes the object
+    pub becomes: Option<WorldObject>,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+pub struct Regrowth {
+    pub into: WorldObject,
+    pub seconds: f32,
+}
+
+#[derive(Deserialize)]
+pub struct HarvestTable {
+    harvestables: StdHashMap<WorldObject, Vec<Harvest>>,
+    regrowth: StdHashMap<WorldObject, Regrowth>,
+}
+
+#[derive(Component)]
+pub struct Regrowing {
+    timer: Timer,
+}
+
+pub struct HarvestPlugin;
+
+impl Plugin for HarvestPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(load_harvest_table(HARVEST_PATH))
+            .add_system(player_harvest.label("harvest"))
+            .add_system(tick_regrowth.label("regrow"))
+            .add_system(start_regrowth.after("harvest").after("regrow"));
+    }
+}
+
+pub fn load_harvest_table(path: &str) -> HarvestTable {
+    let contents = read_asset(path);
+    parse_harvest_table(&contents)
+        .unwrap_or_else(|err| panic!("Invalid harvest file {}: {}", path, err))
+}
+
+pub fn parse_harvest_table(contents: &str) -> Result<HarvestTable, String> {
+    let table: HarvestTable = ron::de::from_str(contents).map_err(|err| err.to_string())?;
+    for (object, harvests) in table.harvestables.iter() {
+        if let WorldObject::Item(_) = object {
+            return Err(format!("{:?} is an item, pick it up instead", object));
+        }
+        for (i, harvest) in harvests.iter().enumerate() {
+            if harvest.count == 0 {
+                return Err(format!("harvesting {:?} yields nothing", object));
+            }
+            if let Some(WorldObject::Item(_)) = harvest.becomes {
+                return Err(format!("{:?} can't become an item", object));
+            }
+            if harvests[..i].iter().any(|other| other.tool == harvest.tool) {
+                return Err(format!(
+                    "{:?} has two ways to harvest it with {:?}",
+                    object, harvest.tool
+                ));
+            }
+        }
+    }
+    for (object, regrowth) in table.regrowth.iter() {
+        if regrowth.seconds <= 0.0 {
+            return Err(format!("{:?} needs a positive regrowth time", object));
+        }
+        if let WorldObject::Item(_) = regrowth.into {
+            return Err(format!("{:?} can't regrow into an item", object));
+        }
+    }
+    Ok(table)
+}
+
+impl HarvestTable {
+    pub fn is_harvestable(&self, object: WorldObject) -> bool {
+        self.harvestables.contains_key(&object)
+    }
+
+    /// Objects the interact key does something to, campfires take fuel and chests open
+    pub fn is_interactable(&self, object: WorldObject) -> bool {
+        self.is_harvestable(object) || matches!(object, WorldObject::CampFire | WorldObject::Chest)
+    }
+
+    /// How an object is harvested with the held tool, falling back to bare hands
+    pub fn harvest(&self, object: WorldObject, tool: Option<Tool>) -> Option<&Harvest> {
+        let harvests = self.harvestables.get(&object)?;
+        harvests
+            .iter()
+            .find(|harvest| tool.is_some() && harvest.tool == tool)
+            .or_else(|| harvests.iter().find(|harvest| harvest.tool.is_none()))
+    }
+
+    /// Tools that can harvest an object, used to tell the player what they are missing
+    pub fn tools_for(&self, object: WorldObject) -> Vec<Tool> {
+        self.harvestables
+            .get(&object)
+            .map(|harvests| harvests.iter().filter_map(|harvest| harvest.tool).collect())
+            .unwrap_or_default()
+    }
+
+    pub fn regrowth(&self, object: WorldObject) -> Option<&Regrowth> {
+        self.regrowth.get(&object)
+    }
+}
+
+/// What the interact key acts on: the closest interactable object within arm's reach.
+/// Harvesting, fueling and opening chests all pick through here so one press does one thing
+pub fn nearest_interactable(
+    table: &HarvestTable,
+    player: &Player,
+    player_pos: Vec2,
+    objects: impl Iterator<Item = (Entity, Vec2, WorldObject)>,
+) -> Option<Entity> {
+    objects
+        .filter(|(_, _, object)| table.is_interactable(*object))
+        .map(|(ent, position, _)| (Vec2::distance(position, player_pos), ent))
+        .filter(|(distance, _)| *distance < player.arm_length)
+        .min_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap())
+        .map(|(_, ent)| ent)
+}
+
+fn player_harvest(
+    mut commands: Commands,
+    keyboard: Res<Input<KeyCode>>,
+    graphics: Res<PlaceHolderGraphics>,
+    registry: Res<ItemRegistry>,
+    table: Res<HarvestTable>,
+    mut pickup_events: EventWriter<ItemPickedUp>,
+    mut inventory_events: InventoryEvents,
+    mut player_query: Query<(Entity, &Transform, &Player, &mut Inventory)>,
+    mut object_query: Query<(Entity, &Transform, &mut Object), Without<Player>>,
+) {
+    if !keyboard.just_pressed(INTERACT_KEY) {
+        return;
+    }
+    let (player_ent, player_transform, player, mut inventory) = player_query.single_mut();
+    let player_pos = player_transform.translation.truncate();
+
+    //Campfires and chests are interacted with too, they take fuel or open instead
+    let objects = object_query
+        .iter_mut()
+        .map(|(ent, transform, object)| (ent, transform.translation.truncate(), object.object));
+    let nearest = nearest_interactable(&table, player, player_pos, objects);
+
+    if let Some((object_ent, _, mut object)) =
+        nearest.and_then(|ent| object_query.get_mut(ent).ok())
+    {
+        if !table.is_harvestable(object.object) {
+            return;
+        }
+        let held_tool = inventory.selected_item().tool();
+        let harvest = match table.harvest(object.object, held_tool) {
+            Some(harvest) => *harvest,
+            None => {
+                info!(
+                    "Need one of {:?} selected to harvest {:?}",
+                    table.tools_for(object.object),
+                    object.object
+                );
+                return;
+            }
+        };
+        if harvest.tool.is_some() {
+            wear_selected_tool(player_ent, &mut inventory, &registry, &mut inventory_events);
+        }
+        give_or_drop(
+            player_ent,
+            &mut inventory,
+            harvest.yields,
+            harvest.count,
+            player_pos,
+            &mut commands,
+            &graphics,
+            &registry,
+            &mut inventory_events,
+        );
+        pickup_events.send(ItemPickedUp {
+            picker: player_ent,
+            item: harvest.yields,
+        });
+        match harvest.becomes {
+            Some(becomes) => object.object = becomes,
+            None => commands.entity(object_ent).despawn_recursive(),
+        }
+    }
+}
+
+/// Objects that can grow back get a timer whenever they become that object
+fn start_regrowth(
+    mut commands: Commands,
+    table: Res<HarvestTable>,
+    object_query: Query<(Entity, &Object), Changed<Object>>,
+) {
+    for (ent, object) in object_query.iter() {
+        match table.regrowth(object.object) {
+            Some(regrowth) => {
+                commands.entity(ent).insert(Regrowing {
+                    timer: Timer::from_seconds(regrowth.seconds, false),
+                });
+            }
+            None => {
+                commands.entity(ent).remove::<Regrowing>();
+            }
+        }
+    }
+}
+
+fn tick_regrowth(
+    time: Res<Time>,
+    table: Res<HarvestTable>,
+    mut object_query: Query<(&mut Object, &mut Regrowing)>,
+) {
+    for (mut object, mut regrowing) in object_query.iter_mut() {
+        if regrowing.timer.finished() {
+            continue;
+        }
+        regrowing.timer.tick(time.delta());
+        if regrowing.timer.finished() {
+            if let Some(regrowth) = table.regrowth(object.object) {
+                object.object = regrowth.into;
+            }
+        }
+    }
+}
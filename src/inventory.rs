@@ -1,271 +1,906 @@
-use bevy::prelude::*;
+use std::ops::Range;
+
+use bevy::{ecs::system::SystemParam, prelude::*};
 use bevy_inspector_egui::{Inspectable, RegisterInspectable};
+use serde::{Deserialize, Serialize};
 
-pub const INVENTORY_SIZE: usize = 10;
+/// Slots picked with the number keys, always shown at the bottom of the screen
+pub const HOTBAR_SIZE: usize = 10;
+/// Backpack slots the player starts with
+const STARTING_BACKPACK: usize = 10;
+/// Hotbar and backpack together, four rows of backpack above the hotbar
+pub const MAX_CAPACITY: usize = 50;
+pub const BACKPACK_KEY: KeyCode = KeyCode::Tab;
+const DURABILITY_BAR_WIDTH: f32 = 40.0;
+const BOX_SIZE: f32 = 50.0;
+pub const BOX_SPACING: f32 = 84.0;
+pub const HOTBAR_Y: f32 = -260.0;
 
 use crate::{
+    chest::ChestMenu,
+    crafting::CraftingMenu,
     graphics::PlaceHolderGraphics,
-    items::{dropped_item, spawn_item, ItemData, ItemType, Pickupable},
-    player::Player,
-    GameCamera, RESOLUTION,
+    items::{dropped_item, ItemType, Pickupable, WorldObject},
+    player::{Player, USE_KEY},
+    registry::ItemRegistry,
+    GameCamera,
 };
 
-#[derive(Component, Default, Inspectable)]
+/// The hotbar is the first HOTBAR_SIZE slots, the backpack the rest
+#[derive(Component, Inspectable, Clone, Serialize, Deserialize)]
 pub struct Inventory {
-    pub items: [InventoryEntry; INVENTORY_SIZE],
+    pub items: Vec<InventoryEntry>,
     selected: usize,
 }
 
-#[derive(Default, Inspectable)]
+impl Default for Inventory {
+    fn default() -> Self {
+        Inventory::new(HOTBAR_SIZE + STARTING_BACKPACK)
+    }
+}
+
+#[derive(Default, Inspectable, Clone, Serialize, Deserialize)]
 pub struct InventoryEntry {
-    pub item: ItemData,
+    pub item: ItemType,
     pub count: usize,
+    /// Times the top tool in this slot has been used
+    pub used: u32,
 }
 
-#[derive(Component)]
-pub struct UiCountText {
-    slot: usize,
+impl Inventory {
+    pub fn new(capacity: usize) -> Self {
+        Inventory {
+            items: vec![InventoryEntry::default(); capacity],
+            selected: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Adds empty backpack slots, refuses to go past MAX_CAPACITY
+    pub fn grow(&mut self, slots: usize) -> bool {
+        if self.capacity() + slots > MAX_CAPACITY {
+            return false;
+        }
+        self.items
+            .extend(std::iter::repeat_n(InventoryEntry::default(), slots));
+        true
+    }
+
+    pub fn selected_slot(&self) -> usize {
+        self.selected
+    }
+
+    pub fn selected_item(&self) -> ItemType {
+        self.items[self.selected].item
+    }
+
+    /// Takes one item out of the selected slot
+    pub fn take_selected(&mut self) -> Option<ItemType> {
+        let slot = &mut self.items[self.selected];
+        if slot.count == 0 {
+            return None;
+        }
+        let item = slot.item;
+        slot.count -= 1;
+        if slot.count == 0 {
+            *slot = InventoryEntry::default();
+        }
+        Some(item)
+    }
+
+    /// Adds up to `amount` of an item, topping up its unfilled stacks before starting
+    /// new ones in empty slots. Returns how many didn't fit
+    pub fn add(&mut self, to_give: ItemType, amount: usize, registry: &ItemRegistry) -> usize {
+        let stack_size = registry.stack_size(to_give);
+        let mut left = amount;
+        //Add to item count if item is already in inventory
+        for slot in self.items.iter_mut() {
+            if left == 0 {
+                break;
+            }
+            if slot.item == to_give && slot.count < stack_size {
+                let added = left.min(stack_size - slot.count);
+                slot.count += added;
+                left -= added;
+            }
+        }
+        //Start new stacks with the rest
+        for slot in self.items.iter_mut() {
+            if left == 0 {
+                break;
+            }
+            if slot.item == ItemType::None {
+                let added = left.min(stack_size);
+                slot.item = to_give;
+                slot.count = added;
+                slot.used = 0;
+                left -= added;
+            }
+        }
+        left
+    }
+
+    /// Removes exactly `amount` of an item, taking from as many slots as needed,
+    /// or removes nothing if there isn't enough
+    pub fn remove(&mut self, to_remove: ItemType, amount: usize) -> bool {
+        if count_item(self, to_remove) < amount {
+            return false;
+        }
+        let mut left = amount;
+        for slot in self.items.iter_mut() {
+            if left == 0 {
+                break;
+            }
+            if slot.item == to_remove {
+                let taken = left.min(slot.count);
+                slot.count -= taken;
+                left -= taken;
+                if slot.count == 0 {
+                    slot.item = ItemType::None;
+                    slot.used = 0;
+                }
+            }
+        }
+        true
+    }
+
+    /// Empties a slot, returning its whole stack
+    pub fn take_slot(&mut self, slot: usize) -> InventoryEntry {
+        std::mem::take(&mut self.items[slot])
+    }
+
+    /// Takes the bigger half of a stack out of its slot
+    pub fn split_slot(&mut self, slot: usize) -> InventoryEntry {
+        let entry = &mut self.items[slot];
+        let taken = entry.count.div_ceil(2);
+        if taken == entry.count {
+            return self.take_slot(slot);
+        }
+        entry.count -= taken;
+        InventoryEntry {
+            item: entry.item,
+            count: taken,
+            used: 0,
+        }
+    }
+
+    /// Puts a stack in a slot, merging it into the same item or swapping it
+    /// with anything else. Returns what is left in hand
+    pub fn place_in_slot(
+        &mut self,
+        slot: usize,
+        mut stack: InventoryEntry,
+        registry: &ItemRegistry,
+    ) -> InventoryEntry {
+        let entry = &mut self.items[slot];
+        if stack.count == 0 {
+            return InventoryEntry::default();
+        }
+        if entry.count == 0 {
+            *entry = stack;
+            return InventoryEntry::default();
+        }
+        if entry.item != stack.item {
+            return std::mem::replace(entry, stack);
+        }
+        let room = registry.stack_size(stack.item).saturating_sub(entry.count);
+        let moved = room.min(stack.count);
+        entry.count += moved;
+        stack.count -= moved;
+        if stack.count == 0 {
+            return InventoryEntry::default();
+        }
+        stack
+    }
+
+    /// Puts a stack into some of the slots, onto stacks of the same item first and
+    /// then into the first empty one. Returns what didn't fit
+    pub fn merge_into(
+        &mut self,
+        mut stack: InventoryEntry,
+        slots: Range<usize>,
+        registry: &ItemRegistry,
+    ) -> InventoryEntry {
+        for i in slots.clone() {
+            if self.items[i].item == stack.item {
+                stack = self.place_in_slot(i, stack, registry);
+            }
+        }
+        let empty = slots.into_iter().find(|i| self.items[*i].count == 0);
+        if let Some(i) = empty {
+            stack = self.place_in_slot(i, stack, registry);
+        }
+        stack
+    }
+
+    /// Moves a whole stack between the hotbar and the backpack,
+    /// whatever doesn't fit stays where it was
+    pub fn quick_move(&mut self, slot: usize, registry: &ItemRegistry) {
+        let other_section = if slot < HOTBAR_SIZE {
+            HOTBAR_SIZE..self.capacity()
+        } else {
+            0..HOTBAR_SIZE
+        };
+        let stack = self.take_slot(slot);
+        self.items[slot] = self.merge_into(stack, other_section, registry);
+    }
 }
 
-#[derive(Component, Inspectable)]
+/// One slot of a container, the player's inventory or a chest
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Slot {
+    pub owner: Entity,
+    pub index: usize,
+}
+
+/// A stack picked up with the mouse, either dragged or held until it is put down
+#[derive(Component, Default)]
+pub struct Hand {
+    pub held: InventoryEntry,
+    /// Slot the stack is being dragged from, None once it's only held
+    dragged_from: Option<Slot>,
+}
+
+/// What the mouse did to the inventory slots, None is anywhere outside them
+pub enum SlotAction {
+    /// Picks up a stack, or puts the held one down
+    Press(Option<Slot>),
+    /// Ends a drag, outside the slots the stack is thrown
+    Release(Option<Slot>),
+    /// Picks up half a stack
+    Split(Slot),
+    /// Moves a stack between the hotbar and the backpack, or between the player and an open chest
+    QuickMove(Slot),
+}
+
+impl SlotAction {
+    fn slot(&self) -> Option<Slot> {
+        match *self {
+            SlotAction::Press(slot) | SlotAction::Release(slot) => slot,
+            SlotAction::Split(slot) | SlotAction::QuickMove(slot) => Some(slot),
+        }
+    }
+}
+
+/// Whether the backpack rows are shown, and for how many slots the boxes were built
+#[derive(Default)]
+pub struct BackpackUi {
+    pub open: bool,
+    built: usize,
+}
+
+#[derive(Component)]
+pub struct UiCountText;
+
+#[derive(Component)]
 pub struct UiBox {
+    owner: Entity,
     slot: usize,
 }
 
 #[derive(Component, Inspectable)]
 pub struct UiBoxContents;
 
+#[derive(Component, Inspectable)]
+pub struct UiDurabilityBar;
+
+/// The stack in hand, follows the cursor
+#[derive(Component)]
+pub struct UiHeldStack;
+
+#[derive(Component)]
+pub struct UiHeldCountText;
+
+pub struct ItemPickedUp {
+    pub picker: Entity,
+    pub item: ItemType,
+}
+
+/// Some of an item went into a container
+pub struct ItemAdded {
+    pub container: Entity,
+    pub item: ItemType,
+    pub count: usize,
+}
+
+/// Some of an item was taken out of a container, moving a stack into the hand counts too
+pub struct ItemRemoved {
+    pub container: Entity,
+    pub item: ItemType,
+    pub count: usize,
+}
+
+/// Another hotbar slot was picked with the number keys
+pub struct SlotSelected {
+    pub container: Entity,
+    pub slot: usize,
+}
+
+/// `give_item` ran out of room, `left` of the item didn't fit
+pub struct InventoryFull {
+    pub container: Entity,
+    pub item: ItemType,
+    pub left: usize,
+}
+
+/// Where everything that changes what a container holds reports it
+#[derive(SystemParam)]
+pub struct InventoryEvents<'w, 's> {
+    added: EventWriter<'w, 's, ItemAdded>,
+    removed: EventWriter<'w, 's, ItemRemoved>,
+    full: EventWriter<'w, 's, InventoryFull>,
+}
+
+impl<'w, 's> InventoryEvents<'w, 's> {
+    /// Nothing is sent for a count of 0
+    pub fn send_added(&mut self, container: Entity, item: ItemType, count: usize) {
+        if count > 0 {
+            self.added.send(ItemAdded {
+                container: container,
+                item: item,
+                count: count,
+            });
+        }
+    }
+
+    /// Nothing is sent for a count of 0
+    pub fn send_removed(&mut self, container: Entity, item: ItemType, count: usize) {
+        if count > 0 {
+            self.removed.send(ItemRemoved {
+                container: container,
+                item: item,
+                count: count,
+            });
+        }
+    }
+
+    /// A stack went into a slot and `left` came back out, either what didn't
+    /// merge or the different stack it was swapped with
+    fn send_placed(&mut self, container: Entity, placed: &InventoryEntry, left: &InventoryEntry) {
+        if left.count > 0 && left.item != placed.item {
+            self.send_added(container, placed.item, placed.count);
+            self.send_removed(container, left.item, left.count);
+        } else {
+            self.send_added(container, placed.item, placed.count - left.count);
+        }
+    }
+}
+
 pub struct InventoryPlugin;
 
 impl Plugin for InventoryPlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_system(spawn_inventory_ui)
-            .add_system(player_pickup)
-            .add_system(update_inventory_ui)
+        app.add_system(player_pickup)
             .add_system(drop_item)
+            .add_system(use_bag)
             .add_system(change_inv_select)
+            .add_system(handle_slot_actions.label("slotactions"))
+            .add_event::<ItemPickedUp>()
+            .add_event::<ItemAdded>()
+            .add_event::<ItemRemoved>()
+            .add_event::<SlotSelected>()
+            .add_event::<InventoryFull>()
+            .add_event::<SlotAction>();
+    }
+}
+
+/// The hotbar and backpack, left out when running headless
+pub struct InventoryUiPlugin;
+
+impl Plugin for InventoryUiPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BackpackUi>()
+            .add_startup_system(spawn_inventory_ui)
+            .add_system(toggle_backpack)
+            .add_system(build_slot_boxes)
+            .add_system(inventory_mouse.before("slotactions"))
+            .add_system(update_inventory_ui)
+            .add_system(update_held_stack.after("slotactions"))
             .register_inspectable::<UiBoxContents>()
-            .register_inspectable::<UiBox>();
+            .register_inspectable::<UiDurabilityBar>();
     }
 }
 
-pub fn remove_item(inventory: &mut Inventory, to_remove: ItemType, amount: usize) -> bool {
-    for mut slot in inventory.items.iter_mut() {
-        if slot.item.types == to_remove {
-            if slot.count < amount {
-                return false;
-            } else {
-                slot.count -= amount;
-                if slot.count == 0 {
-                    slot.item.types = ItemType::None;
-                }
-                return true;
-            }
-        }
+/// Total amount of an item across every slot
+pub fn count_item(inventory: &Inventory, item: ItemType) -> usize {
+    inventory
+        .items
+        .iter()
+        .filter(|slot| slot.item == item)
+        .map(|slot| slot.count)
+        .sum()
+}
+
+/// Removes exactly `amount` of an item from a container, or removes nothing if it
+/// doesn't hold enough
+pub fn remove_item(
+    owner: Entity,
+    inventory: &mut Inventory,
+    to_remove: ItemType,
+    amount: usize,
+    events: &mut InventoryEvents,
+) -> bool {
+    if !inventory.remove(to_remove, amount) {
+        return false;
     }
-    false
+    events.send_removed(owner, to_remove, amount);
+    true
+}
+
+/// Takes one item out of the selected slot
+pub fn take_selected_item(
+    owner: Entity,
+    inventory: &mut Inventory,
+    events: &mut InventoryEvents,
+) -> Option<ItemType> {
+    let item = inventory.take_selected()?;
+    events.send_removed(owner, item, 1);
+    Some(item)
 }
 
-pub fn give_item(inventory: &mut Inventory, to_give: ItemType) -> bool {
-    //Add to item count if item is already in inventory
-    for mut slot in inventory.items.iter_mut() {
-        if slot.item.types == to_give {
-            slot.count += 1;
-            return true;
+/// Wears down the tool in the selected slot, a tool that runs out of durability breaks
+pub fn wear_selected_tool(
+    owner: Entity,
+    inventory: &mut Inventory,
+    registry: &ItemRegistry,
+    events: &mut InventoryEvents,
+) {
+    let selected = inventory.selected;
+    let slot = &mut inventory.items[selected];
+    let durability = match registry.durability(slot.item) {
+        Some(durability) => durability,
+        None => return,
+    };
+    slot.used += 1;
+    if slot.used >= durability {
+        info!("{} broke", registry.name(slot.item));
+        events.send_removed(owner, slot.item, 1);
+        slot.used = 0;
+        slot.count -= 1;
+        if slot.count == 0 {
+            slot.item = ItemType::None;
         }
     }
-    //Add item to inventory if you don't have it
-    for mut slot in inventory.items.iter_mut() {
-        if slot.item.types == ItemType::None {
-            slot.item.types = to_give;
-            slot.count = 1;
-            return true;
-        }
+}
+
+/// Adds up to `amount` of an item to a container. Returns how many didn't fit
+pub fn give_item(
+    owner: Entity,
+    inventory: &mut Inventory,
+    to_give: ItemType,
+    amount: usize,
+    registry: &ItemRegistry,
+    events: &mut InventoryEvents,
+) -> usize {
+    let left = inventory.add(to_give, amount, registry);
+    events.send_added(owner, to_give, amount - left);
+    if left > 0 {
+        events.full.send(InventoryFull {
+            container: owner,
+            item: to_give,
+            left: left,
+        });
+    }
+    left
+}
+
+/// Gives items, whatever doesn't fit is dropped at `position`
+pub fn give_or_drop(
+    owner: Entity,
+    inventory: &mut Inventory,
+    item: ItemType,
+    count: usize,
+    position: Vec2,
+    commands: &mut Commands,
+    graphics: &PlaceHolderGraphics,
+    registry: &ItemRegistry,
+    events: &mut InventoryEvents,
+) {
+    let left = give_item(owner, inventory, item, count, registry, events);
+    if left > 0 {
+        dropped_item(item, left, position, commands, graphics, registry);
     }
-    return false;
 }
 
 fn drop_item(
-    commands: Commands,
+    mut commands: Commands,
     graphics: Res<PlaceHolderGraphics>,
+    registry: Res<ItemRegistry>,
     keyboard: Res<Input<KeyCode>>,
-    player_query: Query<&Transform, With<Player>>,
-    mut inventory_query: Query<&mut Inventory>,
+    mut events: InventoryEvents,
+    mut player_query: Query<(Entity, &Transform, &mut Inventory), With<Player>>,
 ) {
-    let mut inventory = inventory_query.single_mut();
-    let selected = inventory.selected;
-    let player_pos = player_query.single().translation;
-    if keyboard.just_pressed(KeyCode::Q) && inventory.items[selected].count != 0 {
-        inventory.items[selected].count -= 1;
-        let mut sprite = TextureAtlasSprite::new(
-            *graphics
-                .item_map
-                .get(&inventory.items[selected].item.types)
-                .expect("No graphic for item"),
-        );
-        sprite.custom_size = Some(Vec2::splat(25.0));
-        dropped_item(
-            commands,
-            graphics,
-            &inventory.items[selected].item,
-            Vec2::new(player_pos.x, player_pos.y),
-            sprite,
-        );
-        inventory.items[selected].item.current_num += 1;
-        if inventory.items[selected].count <= 0 {
-            inventory.items[selected].item.types = ItemType::None;
-        }
-    }
-}
-
-fn change_inv_select(keyboard: Res<Input<KeyCode>>, mut inventory_query: Query<&mut Inventory>) {
-    let mut inventory = inventory_query.single_mut();
-    if keyboard.just_pressed(KeyCode::Key1) {
-        inventory.selected = 0;
-    }
-    if keyboard.just_pressed(KeyCode::Key2) {
-        inventory.selected = 1;
-    }
-    if keyboard.just_pressed(KeyCode::Key3) {
-        inventory.selected = 2;
+    let (player_ent, transform, mut inventory) = player_query.single_mut();
+    let player_pos = transform.translation;
+    if keyboard.just_pressed(KeyCode::Q) {
+        if let Some(item) = take_selected_item(player_ent, &mut inventory, &mut events) {
+            dropped_item(
+                item,
+                1,
+                Vec2::new(player_pos.x, player_pos.y),
+                &mut commands,
+                &graphics,
+                &registry,
+            );
+        }
     }
-    if keyboard.just_pressed(KeyCode::Key4) {
-        inventory.selected = 3;
+}
+
+/// Using a bag adds its slots to the backpack, the bag is kept if there's no room for them
+fn use_bag(
+    keyboard: Res<Input<KeyCode>>,
+    registry: Res<ItemRegistry>,
+    mut events: InventoryEvents,
+    mut inventory_query: Query<(Entity, &mut Inventory), With<Player>>,
+) {
+    if !keyboard.just_pressed(USE_KEY) {
+        return;
     }
-    if keyboard.just_pressed(KeyCode::Key5) {
-        inventory.selected = 4;
+    let (player_ent, mut inventory) = inventory_query.single_mut();
+    let slots = registry.backpack_slots(inventory.selected_item());
+    if slots == 0 {
+        return;
     }
-    if keyboard.just_pressed(KeyCode::Key6) {
-        inventory.selected = 5;
+    if inventory.grow(slots) {
+        take_selected_item(player_ent, &mut inventory, &mut events);
+    } else {
+        info!("The backpack can't get any bigger");
     }
-    if keyboard.just_pressed(KeyCode::Key7) {
-        inventory.selected = 6;
+}
+
+/// Turns clicks on the hotbar, open backpack and open chest into slot actions
+fn inventory_mouse(
+    mouse: Res<Input<MouseButton>>,
+    keyboard: Res<Input<KeyCode>>,
+    windows: Res<Windows>,
+    menu: Res<CraftingMenu>,
+    mut actions: EventWriter<SlotAction>,
+    box_query: Query<(&UiBox, &Transform, &Visibility)>,
+) {
+    let cursor = match cursor_position(&windows) {
+        Some(cursor) => cursor,
+        None => return,
+    };
+    let slot = box_query
+        .iter()
+        .find(|(_, transform, visibility)| {
+            visibility.is_visible
+                && (cursor - transform.translation.truncate())
+                    .abs()
+                    .max_element()
+                    <= BOX_SIZE / 2.0
+        })
+        .map(|(ui_box, _, _)| Slot {
+            owner: ui_box.owner,
+            index: ui_box.slot,
+        });
+    let shift = keyboard.pressed(KeyCode::LShift) || keyboard.pressed(KeyCode::RShift);
+
+    if mouse.just_pressed(MouseButton::Left) {
+        match slot {
+            Some(slot) if shift => actions.send(SlotAction::QuickMove(slot)),
+            Some(slot) => actions.send(SlotAction::Press(Some(slot))),
+            //Clicks on the crafting menu shouldn't throw anything away
+            None if !menu.is_open() => actions.send(SlotAction::Press(None)),
+            None => {}
+        }
     }
-    if keyboard.just_pressed(KeyCode::Key8) {
-        inventory.selected = 7;
+    if mouse.just_released(MouseButton::Left) {
+        actions.send(SlotAction::Release(slot));
     }
-    if keyboard.just_pressed(KeyCode::Key9) {
-        inventory.selected = 8;
+    if mouse.just_pressed(MouseButton::Right) {
+        if let Some(slot) = slot {
+            actions.send(SlotAction::Split(slot));
+        }
     }
-    if keyboard.just_pressed(KeyCode::Key0) {
-        inventory.selected = 9;
+}
+
+/// Cursor position relative to the middle of the screen, where the camera is
+fn cursor_position(windows: &Windows) -> Option<Vec2> {
+    windows.get_primary().and_then(|window| {
+        window
+            .cursor_position()
+            .map(|pos| pos - Vec2::new(window.width(), window.height()) / 2.0)
+    })
+}
+
+/// The inventory a slot belongs to, if it has that many slots
+fn container<'a>(
+    inventory_query: &'a mut Query<&mut Inventory>,
+    slot: Slot,
+) -> Option<Mut<'a, Inventory>> {
+    inventory_query
+        .get_mut(slot.owner)
+        .ok()
+        .filter(|inventory| slot.index < inventory.capacity())
+}
+
+/// Moves a whole stack into another container, whatever doesn't fit stays where it was
+fn move_to_container(
+    from: Slot,
+    to: Entity,
+    inventory_query: &mut Query<&mut Inventory>,
+    registry: &ItemRegistry,
+    events: &mut InventoryEvents,
+) {
+    let stack = match container(inventory_query, from) {
+        Some(mut inventory) => inventory.take_slot(from.index),
+        None => return,
+    };
+    let item = stack.item;
+    let count = stack.count;
+    let left = match inventory_query.get_mut(to) {
+        Ok(mut inventory) => {
+            let slots = 0..inventory.capacity();
+            inventory.merge_into(stack, slots, registry)
+        }
+        Err(_) => stack,
+    };
+    let moved = count - left.count;
+    if let Some(mut inventory) = container(inventory_query, from) {
+        inventory.items[from.index] = left;
     }
+    events.send_removed(from.owner, item, moved);
+    events.send_added(to, item, moved);
 }
 
+/// Puts the held stack down in a slot, what comes back out is held instead
+fn place_held(
+    slot: Slot,
+    hand: &mut Hand,
+    inventory: &mut Inventory,
+    registry: &ItemRegistry,
+    events: &mut InventoryEvents,
+) {
+    let held = std::mem::take(&mut hand.held);
+    let placed = held.clone();
+    hand.held = inventory.place_in_slot(slot.index, held, registry);
+    events.send_placed(slot.owner, &placed, &hand.held);
+}
 
-fn update_inventory_ui(
+fn handle_slot_actions(
     mut commands: Commands,
-    inventory_query: Query<&Inventory>,
     graphics: Res<PlaceHolderGraphics>,
-    box_query: Query<(Entity, Option<&Children>, &UiBox)>,
-    mut box_contents_query: Query<&mut TextureAtlasSprite, With<UiBoxContents>>,
-    mut text_query: Query<(&UiCountText, &mut Text)>,
+    registry: Res<ItemRegistry>,
+    chest_menu: Res<ChestMenu>,
+    mut actions: EventReader<SlotAction>,
+    mut events: InventoryEvents,
+    mut player_query: Query<(Entity, &Transform, &mut Hand), With<Player>>,
+    mut inventory_query: Query<&mut Inventory>,
 ) {
-    let inventory = inventory_query.single();
-    for (i, slot) in inventory.items.iter().enumerate() {
-        for (text_count, mut text) in text_query.iter_mut() {
-            if text_count.slot == i {
-                if slot.count > 0 {
-                    text.sections[0].value = format!("{}", slot.count);
-                } else {
-                    text.sections[0].value = String::new();
-                }
+    let (player_ent, transform, mut hand) = player_query.single_mut();
+    let position = transform.translation.truncate();
+    for action in actions.iter() {
+        //Only the player's own slots and the open chest are in reach
+        if let Some(slot) = action.slot() {
+            if slot.owner != player_ent && Some(slot.owner) != chest_menu.chest {
+                continue;
             }
         }
-        for (box_ent, children, ui_box) in box_query.iter() {
-            if ui_box.slot == i {
-                //Change graphic if there is a graphic
-                if slot.count != 0 {
-                    match children {
-                        Some(children) => {
-                            for child in children.iter() {
-                                let mut sprite = box_contents_query
-                                    .get_mut(*child)
-                                    .expect("Nonsprite child of box");
-
-                                sprite.index = *graphics
-                                    .item_map
-                                    .get(&slot.item.types)
-                                    .expect("Error: No graphics for item");
+        let holding = hand.held.count > 0;
+        let mut throw = false;
+        match *action {
+            SlotAction::Press(Some(slot)) if !holding => {
+                if let Some(mut inventory) = container(&mut inventory_query, slot) {
+                    if inventory.items[slot.index].count > 0 {
+                        hand.held = inventory.take_slot(slot.index);
+                        hand.dragged_from = Some(slot);
+                        events.send_removed(slot.owner, hand.held.item, hand.held.count);
+                    }
+                }
+            }
+            SlotAction::Press(Some(slot)) => {
+                if let Some(mut inventory) = container(&mut inventory_query, slot) {
+                    place_held(slot, &mut hand, &mut inventory, &registry, &mut events);
+                    hand.dragged_from = None;
+                }
+            }
+            SlotAction::Press(None) => throw = holding,
+            SlotAction::Release(slot) => {
+                //Letting go where the drag started keeps holding the stack
+                if let Some(from) = hand.dragged_from.take() {
+                    match slot {
+                        Some(slot) if slot != from => {
+                            if let Some(mut inventory) = container(&mut inventory_query, slot) {
+                                place_held(slot, &mut hand, &mut inventory, &registry, &mut events);
                             }
                         }
-                        None => {
-                            let mut sprite = TextureAtlasSprite::new(
-                                *graphics
-                                    .item_map
-                                    .get(&slot.item.types)
-                                    .expect("Error: No graphics for item"),
-                            );
-                            sprite.custom_size = Some(Vec2::splat(25.0));
-                            let graphic = commands
-                                .spawn_bundle(SpriteSheetBundle {
-                                    sprite: sprite,
-                                    texture_atlas: graphics.texture_atlas.clone(),
-                                    ..Default::default()
-                                })
-                                .insert(Name::new("ItemGraphic"))
-                                .insert(UiBoxContents)
-                                .id();
-                            commands.entity(box_ent).add_child(graphic);
-                        }
+                        Some(_) => {}
+                        None => throw = holding,
                     }
-                } else if let Some(children) = children {
-                    //Slot empty, we despawn the children
-                    for child in children.iter() {
-                        if box_contents_query.get(*child).is_ok() {
-                            commands.entity(*child).despawn_recursive();
-                            let sprite = TextureAtlasSprite::new(graphics.none_index);
-                            let new_child = commands
-                                .spawn_bundle(SpriteSheetBundle {
-                                    sprite: sprite,
-                                    texture_atlas: graphics.texture_atlas.clone(),
-                                    ..Default::default()
-                                })
-                                .insert(Name::new("ItemGraphic"))
-                                .insert(UiBoxContents)
-                                .id();
-                            commands.entity(box_ent).add_child(new_child);
-                        }
+                }
+            }
+            SlotAction::Split(slot) if !holding => {
+                if let Some(mut inventory) = container(&mut inventory_query, slot) {
+                    if inventory.items[slot.index].count > 0 {
+                        hand.held = inventory.split_slot(slot.index);
+                        events.send_removed(slot.owner, hand.held.item, hand.held.count);
                     }
                 }
             }
+            SlotAction::QuickMove(slot) if !holding => match chest_menu.chest {
+                Some(chest) if slot.owner == player_ent => {
+                    move_to_container(slot, chest, &mut inventory_query, &registry, &mut events)
+                }
+                Some(_) => move_to_container(
+                    slot,
+                    player_ent,
+                    &mut inventory_query,
+                    &registry,
+                    &mut events,
+                ),
+                //Moving within the inventory doesn't change what it holds
+                None => {
+                    if let Some(mut inventory) = container(&mut inventory_query, slot) {
+                        inventory.quick_move(slot.index, &registry);
+                    }
+                }
+            },
+            SlotAction::Split(_) | SlotAction::QuickMove(_) => {}
+        }
+        if throw {
+            let held = std::mem::take(&mut hand.held);
+            dropped_item(
+                held.item,
+                held.count,
+                position,
+                &mut commands,
+                &graphics,
+                &registry,
+            );
+            hand.dragged_from = None;
         }
     }
 }
 
-fn spawn_inventory_ui(
+fn change_inv_select(
+    keyboard: Res<Input<KeyCode>>,
+    mut select_events: EventWriter<SlotSelected>,
+    mut inventory_query: Query<(Entity, &mut Inventory), With<Player>>,
+) {
+    let (player_ent, mut inventory) = inventory_query.single_mut();
+    let mut selected = inventory.selected;
+    if keyboard.just_pressed(KeyCode::Key1) {
+        selected = 0;
+    }
+    if keyboard.just_pressed(KeyCode::Key2) {
+        selected = 1;
+    }
+    if keyboard.just_pressed(KeyCode::Key3) {
+        selected = 2;
+    }
+    if keyboard.just_pressed(KeyCode::Key4) {
+        selected = 3;
+    }
+    if keyboard.just_pressed(KeyCode::Key5) {
+        selected = 4;
+    }
+    if keyboard.just_pressed(KeyCode::Key6) {
+        selected = 5;
+    }
+    if keyboard.just_pressed(KeyCode::Key7) {
+        selected = 6;
+    }
+    if keyboard.just_pressed(KeyCode::Key8) {
+        selected = 7;
+    }
+    if keyboard.just_pressed(KeyCode::Key9) {
+        selected = 8;
+    }
+    if keyboard.just_pressed(KeyCode::Key0) {
+        selected = 9;
+    }
+    //Only an actual change counts, so pressing the selected slot's key again does nothing
+    if selected != inventory.selected {
+        inventory.selected = selected;
+        select_events.send(SlotSelected {
+            container: player_ent,
+            slot: selected,
+        });
+    }
+}
+
+fn toggle_backpack(keyboard: Res<Input<KeyCode>>, mut backpack: ResMut<BackpackUi>) {
+    if keyboard.just_pressed(BACKPACK_KEY) {
+        backpack.open = !backpack.open;
+    }
+}
+
+/// Where a slot's box sits, the hotbar along the bottom and backpack rows stacked above it
+fn slot_position(slot: usize) -> Vec2 {
+    let column = (slot % HOTBAR_SIZE) as f32;
+    let row = (slot / HOTBAR_SIZE) as f32;
+    Vec2::new(
+        (column - HOTBAR_SIZE as f32 / 2.0 + 0.5) * BOX_SPACING,
+        HOTBAR_Y + row * BOX_SPACING,
+    )
+}
+
+/// Builds a box for every slot, again whenever the inventory changes size
+fn build_slot_boxes(
     mut commands: Commands,
     graphics: Res<PlaceHolderGraphics>,
-    camera_query: Query<Entity, With<GameCamera>>,
     asset_server: Res<AssetServer>,
+    mut backpack: ResMut<BackpackUi>,
+    inventory_query: Query<(Entity, &Inventory), With<Player>>,
+    camera_query: Query<Entity, With<GameCamera>>,
+    box_query: Query<(Entity, &UiBox)>,
 ) {
-    let camera_ent = camera_query.single();
-
-    let mut boxes = Vec::new();
-    let mut ui_texts = Vec::new();
-
-    let spacing = 84.0;
-    let spacing_percent = spacing / 3.6 / RESOLUTION / 2.0;
+    let (player_ent, inventory) = inventory_query.single();
+    let capacity = inventory.capacity();
+    if capacity == backpack.built {
+        return;
+    }
+    backpack.built = capacity;
+    for (ent, ui_box) in box_query.iter() {
+        if ui_box.owner == player_ent {
+            commands.entity(ent).despawn_recursive();
+        }
+    }
 
-    let starting_x = (-(INVENTORY_SIZE as f32) / 2.0 + 0.5) * spacing;
-    let starting_percent = 125.5 + starting_x / RESOLUTION / 2.0;
+    let boxes: Vec<Entity> = (0..capacity)
+        .map(|i| {
+            let slot = Slot {
+                owner: player_ent,
+                index: i,
+            };
+            spawn_slot_box(
+                slot,
+                slot_position(i),
+                &mut commands,
+                &graphics,
+                &asset_server,
+            )
+        })
+        .collect();
+    commands.entity(camera_query.single()).push_children(&boxes);
+}
 
+/// A box showing one slot of a container, its position is relative to the camera
+pub fn spawn_slot_box(
+    slot: Slot,
+    position: Vec2,
+    commands: &mut Commands,
+    graphics: &PlaceHolderGraphics,
+    asset_server: &AssetServer,
+) -> Entity {
     let mut sprite = TextureAtlasSprite::new(graphics.box_index);
-    sprite.custom_size = Some(Vec2::splat(50.0));
-    for i in 0..INVENTORY_SIZE {
-        ui_texts.push(
-            commands
-                .spawn_bundle(TextBundle {
-                    style: Style {
-                        align_self: AlignSelf::FlexEnd,
-                        position_type: PositionType::Absolute,
-                        position: Rect {
-                            bottom: Val::Percent(11.0),
-                            left: Val::Percent(starting_percent + spacing_percent * i as f32),
-                            ..Default::default()
-                        },
-                        ..Default::default()
-                    },
+    sprite.custom_size = Some(Vec2::splat(BOX_SIZE));
+    commands
+        .spawn_bundle(SpriteSheetBundle {
+            sprite: sprite,
+            texture_atlas: graphics.texture_atlas.clone(),
+            transform: Transform::from_translation(position.extend(-1.0)),
+            ..Default::default()
+        })
+        .insert(UiBox {
+            owner: slot.owner,
+            slot: slot.index,
+        })
+        .insert(Name::new("Inventory Box"))
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(SpriteSheetBundle {
+                    sprite: graphics.item_sprite(ItemType::None),
+                    texture_atlas: graphics.texture_atlas.clone(),
+                    visibility: Visibility { is_visible: false },
+                    ..Default::default()
+                })
+                .insert(Name::new("ItemGraphic"))
+                .insert(UiBoxContents);
+            parent
+                .spawn_bundle(SpriteBundle {
+                    transform: Transform::from_xyz(0.0, -18.0, 0.2),
+                    visibility: Visibility { is_visible: false },
+                    ..Default::default()
+                })
+                .insert(Name::new("DurabilityBar"))
+                .insert(UiDurabilityBar);
+            parent
+                .spawn_bundle(Text2dBundle {
                     text: Text::with_section(
                         "",
                         TextStyle {
@@ -275,67 +910,240 @@ fn spawn_inventory_ui(
                         },
                         TextAlignment {
                             horizontal: HorizontalAlign::Center,
-                            ..Default::default()
+                            vertical: VerticalAlign::Center,
                         },
                     ),
+                    transform: Transform::from_xyz(14.0, -12.0, 0.3),
                     ..Default::default()
                 })
-                .insert(UiCountText { slot: i })
                 .insert(Name::new("Inventory Count"))
-                .id(),
-        );
+                .insert(UiCountText);
+        })
+        .id()
+}
 
-        boxes.push(
-            commands
-                .spawn_bundle(SpriteSheetBundle {
-                    sprite: sprite.clone(),
-                    texture_atlas: graphics.texture_atlas.clone(),
-                    transform: Transform {
-                        translation: Vec3::new(starting_x + spacing * i as f32, -260.0, -1.0),
-                        ..Default::default()
-                    },
-                    ..Default::default()
-                })
-                .insert(UiBox { slot: i })
-                .id(),
-        );
+/// Redraws the boxes of inventories that changed, all of them when the backpack or a chest opens
+fn update_inventory_ui(
+    player_query: Query<Entity, With<Player>>,
+    inventory_query: Query<(&Inventory, ChangeTrackers<Inventory>)>,
+    backpack: Res<BackpackUi>,
+    chest_menu: Res<ChestMenu>,
+    graphics: Res<PlaceHolderGraphics>,
+    registry: Res<ItemRegistry>,
+    mut box_query: Query<(
+        &Children,
+        &UiBox,
+        ChangeTrackers<UiBox>,
+        &mut TextureAtlasSprite,
+        &mut Visibility,
+    )>,
+    mut box_contents_query: Query<
+        (&mut TextureAtlasSprite, &mut Visibility),
+        (With<UiBoxContents>, Without<UiBox>),
+    >,
+    mut bar_query: Query<
+        (&mut Sprite, &mut Transform, &mut Visibility),
+        (
+            With<UiDurabilityBar>,
+            Without<UiBoxContents>,
+            Without<UiBox>,
+        ),
+    >,
+    mut text_query: Query<
+        (&mut Text, &mut Visibility),
+        (
+            With<UiCountText>,
+            Without<UiDurabilityBar>,
+            Without<UiBoxContents>,
+            Without<UiBox>,
+        ),
+    >,
+) {
+    let player_ent = player_query.single();
+    //An open chest shows the whole inventory next to it
+    let backpack_open = backpack.open || chest_menu.chest.is_some();
+    let toggled = backpack.is_changed() || chest_menu.is_changed();
+    for (children, ui_box, box_tracker, mut box_sprite, mut box_visibility) in box_query.iter_mut()
+    {
+        //Boxes of the old size or a closed chest are still around until they are despawned
+        let (inventory, tracker) = match inventory_query.get(ui_box.owner) {
+            Ok(inventory) => inventory,
+            Err(_) => continue,
+        };
+        if !(toggled || tracker.is_changed() || box_tracker.is_added()) {
+            continue;
+        }
+        let slot = match inventory.items.get(ui_box.slot) {
+            Some(slot) => slot,
+            None => continue,
+        };
+        let is_player = ui_box.owner == player_ent;
+        //Children don't inherit visibility, so a closed backpack hides each of them too
+        let shown = !is_player || ui_box.slot < HOTBAR_SIZE || backpack_open;
+        box_visibility.is_visible = shown;
+        box_sprite.index = if is_player && ui_box.slot == inventory.selected {
+            graphics.selected_box_index
+        } else {
+            graphics.box_index
+        };
+        for child in children.iter() {
+            //Empty slots hide the graphic until something goes back in
+            if let Ok((mut sprite, mut visibility)) = box_contents_query.get_mut(*child) {
+                sprite.index = graphics.item_index(slot.item);
+                sprite.color = graphics.object_color(WorldObject::Item(slot.item));
+                visibility.is_visible = shown && slot.count != 0;
+            }
+            //Only worn tools show how much use they have left
+            if let Ok((mut sprite, mut transform, mut visibility)) = bar_query.get_mut(*child) {
+                let durability = registry.durability(slot.item).filter(|_| slot.count != 0);
+                match durability {
+                    Some(durability) if slot.used > 0 => {
+                        let left = 1.0 - slot.used as f32 / durability as f32;
+                        sprite.custom_size = Some(Vec2::new(DURABILITY_BAR_WIDTH * left, 4.0));
+                        sprite.color = Color::rgb(1.0 - left, left, 0.0);
+                        transform.translation.x = -DURABILITY_BAR_WIDTH * (1.0 - left) / 2.0;
+                        visibility.is_visible = shown;
+                    }
+                    _ => visibility.is_visible = false,
+                }
+            }
+            if let Ok((mut text, mut visibility)) = text_query.get_mut(*child) {
+                text.sections[0].value = match slot.count {
+                    0 => String::new(),
+                    count => format!("{}", count),
+                };
+                visibility.is_visible = shown;
+            }
+        }
     }
+}
+
+fn spawn_inventory_ui(
+    mut commands: Commands,
+    graphics: Res<PlaceHolderGraphics>,
+    camera_query: Query<Entity, With<GameCamera>>,
+    asset_server: Res<AssetServer>,
+) {
+    let camera_ent = camera_query.single();
+
+    //Above the hotbar
+    let held = commands
+        .spawn_bundle(SpriteSheetBundle {
+            sprite: graphics.item_sprite(ItemType::None),
+            texture_atlas: graphics.texture_atlas.clone(),
+            transform: Transform::from_xyz(0.0, 0.0, -0.5),
+            visibility: Visibility { is_visible: false },
+            ..Default::default()
+        })
+        .insert(UiHeldStack)
+        .insert(Name::new("HeldStack"))
+        .id();
+    commands.entity(camera_ent).add_child(held);
     commands
-        .spawn_bundle(NodeBundle {
+        .spawn_bundle(TextBundle {
             style: Style {
-                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
-                justify_content: JustifyContent::SpaceBetween,
+                position_type: PositionType::Absolute,
                 ..Default::default()
             },
-            color: Color::NONE.into(),
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/QuattrocentoSans-Regular.ttf"),
+                    font_size: 20.0,
+                    color: Color::BLACK,
+                },
+                Default::default(),
+            ),
             ..Default::default()
         })
-        .push_children(&ui_texts)
-        .insert(Name::new("Inventory Text"));
-    commands.entity(camera_ent).push_children(&boxes);
+        .insert(UiHeldCountText)
+        .insert(Name::new("Held Count"));
+}
+
+fn update_held_stack(
+    windows: Res<Windows>,
+    graphics: Res<PlaceHolderGraphics>,
+    hand_query: Query<&Hand, With<Player>>,
+    mut held_query: Query<
+        (&mut TextureAtlasSprite, &mut Transform, &mut Visibility),
+        With<UiHeldStack>,
+    >,
+    mut text_query: Query<(&mut Text, &mut Style), With<UiHeldCountText>>,
+) {
+    let hand = hand_query.single();
+    let window = windows.get_primary();
+    let cursor = window.and_then(|window| window.cursor_position());
+    let (mut sprite, mut transform, mut visibility) = held_query.single_mut();
+    let (mut text, mut style) = text_query.single_mut();
+    let (cursor, window) = match (cursor, window) {
+        (Some(cursor), Some(window)) if hand.held.count > 0 => (cursor, window),
+        _ => {
+            visibility.is_visible = false;
+            text.sections[0].value = String::new();
+            return;
+        }
+    };
+    let from_center = cursor - Vec2::new(window.width(), window.height()) / 2.0;
+    visibility.is_visible = true;
+    sprite.index = graphics.item_index(hand.held.item);
+    sprite.color = graphics.object_color(WorldObject::Item(hand.held.item));
+    transform.translation.x = from_center.x;
+    transform.translation.y = from_center.y;
+    //UI positions count from the bottom left corner like the cursor
+    style.position = Rect {
+        left: Val::Px(cursor.x + 8.0),
+        bottom: Val::Px(cursor.y - 24.0),
+        ..Default::default()
+    };
+    text.sections[0].value = match hand.held.count {
+        1 => String::new(),
+        count => format!("{}", count),
+    };
 }
 
+/// Picks up what fits of every stack in reach, the rest stays on the ground
 fn player_pickup(
     mut commands: Commands,
     keyboard: Res<Input<KeyCode>>,
-    mut player_query: Query<(&Transform, &Player, &mut Inventory)>,
-    pickupable_query: Query<(Entity, &Transform, &Pickupable), Without<Player>>,
+    registry: Res<ItemRegistry>,
+    mut pickup_events: EventWriter<ItemPickedUp>,
+    mut inventory_events: InventoryEvents,
+    mut player_query: Query<(Entity, &Transform, &Player, &mut Inventory)>,
+    mut pickupable_query: Query<(Entity, &Transform, &mut Pickupable), Without<Player>>,
 ) {
     //TODO Walk towards item when picking it up
-    let (player_transform, player, mut inventory) = player_query.single_mut();
+    let (player_ent, player_transform, player, mut inventory) = player_query.single_mut();
     if keyboard.just_pressed(KeyCode::E) {
         //TODO Pickup the nearest item not first
-        for (ent, transform, pickup) in pickupable_query.iter() {
+        for (ent, transform, mut pickup) in pickupable_query.iter_mut() {
             if player.arm_length
-                > Vec2::distance(
+                <= Vec2::distance(
                     transform.translation.truncate(),
                     player_transform.translation.truncate(),
                 )
             {
-                if give_item(&mut inventory, pickup.item) {
-                    commands.entity(ent).despawn_recursive();
-                }
+                continue;
+            }
+            let left = give_item(
+                player_ent,
+                &mut inventory,
+                pickup.item,
+                pickup.count,
+                &registry,
+                &mut inventory_events,
+            );
+            if left == pickup.count {
+                continue;
+            }
+            if left == 0 {
+                commands.entity(ent).despawn_recursive();
+            } else {
+                pickup.count = left;
             }
+            pickup_events.send(ItemPickedUp {
+                picker: player_ent,
+                item: pickup.item,
+            });
         }
     }
 }
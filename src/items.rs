@@ -1,89 +1,162 @@
-use std::time::Duration;
-
 use bevy::{
-    ecs::system::EntityCommands,
-    prelude::{self, Commands, *},
+    prelude::{Commands, *},
+    utils::HashMap,
 };
 use bevy_inspector_egui::Inspectable;
-use rand::{thread_rng, Rng};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
 
-use crate::{graphics::PlaceHolderGraphics, player::Player};
+use crate::{
+    graphics::PlaceHolderGraphics,
+    player::Player,
+    registry::{load_item_registry, ItemRegistry, SpawnRule, ITEMS_PATH},
+    rng::GameRng,
+    worldgen::WorldGen,
+    GameCamera, HEIGHT, RESOLUTION,
+};
 
+/// A stack of items lying in the world
 #[derive(Component, Inspectable)]
 pub struct Pickupable {
-    pub(crate) item: ItemType,
+    pub item: ItemType,
+    pub count: usize,
 }
 
 #[derive(Component, Inspectable)]
 pub struct Object {
-    pub(crate) item: ItemType,
+    pub object: WorldObject,
 }
 
+#[derive(Component)]
+pub struct ObjectSprite;
+
 pub struct ItemPlugin;
 
-#[derive(Component, Default, Inspectable, PartialEq, Eq, Clone, Copy, Hash)]
+/// Natural spawns stay within the chunks loaded around the player, parse_world_gen makes sure
+/// load_radius reaches this far
+pub const MAX_SPAWN_DISTANCE: f32 = 1000.0;
+const SPAWN_SPOT_TRIES: usize = 8;
+
+#[derive(
+    Component, Default, Inspectable, PartialEq, Eq, Clone, Copy, Hash, Debug, Serialize, Deserialize,
+)]
 pub enum ItemType {
     #[default]
     None,
     Flint,
-    Axe,
+    Tool(Tool),
     Twig,
     Grass,
     Wood,
     PineCone,
     Fire,
     ChoppedPineCone,
+    CookedPineCone,
+    Bag,
 
     Default,
 }
 
-#[derive(Default, Inspectable, Clone)]
-pub struct ItemData {
-    pub types: ItemType,
-    pub name: String,
-    pub graphics: usize,
-    pub pickupable: bool,
+impl ItemType {
+    /// The kind of tool this item is, if it is one
+    pub fn tool(&self) -> Option<Tool> {
+        match self {
+            ItemType::Tool(tool) => Some(*tool),
+            _ => None,
+        }
+    }
+}
 
-    pub max_num: u32,
-    pub current_num: u32,
+#[derive(Default, Inspectable, PartialEq, Eq, Clone, Copy, Hash, Debug, Serialize, Deserialize)]
+pub enum Tool {
+    #[default]
+    Axe,
+    Shovel,
 }
 
-#[derive(Inspectable)]
-pub struct Items {
-    pub flint: ItemData,
+/// Anything that can exist in the world, either as a loose item or a placed object
+#[derive(Inspectable, PartialEq, Eq, Clone, Copy, Hash, Debug, Serialize, Deserialize)]
+pub enum WorldObject {
+    Item(ItemType),
+    Sapling,
+    DeadSapling,
+    Grass,
+    PluckedGrass,
+    Tree,
+    /// A tree whose pine cones have been picked
+    BareTree,
+    GrowingTree,
+    Stump,
+    CampFire,
+    Chest,
 }
 
-impl Plugin for ItemPlugin {
-    fn build(&self, app: &mut App) {
-        app.add_startup_system(get_item.label("getitems"))
-            .add_system(natural_spawn_flint.after("getitems"));
+/// Naturally spawned items still in the world, counted against their rule's max_alive.
+/// Ones in stored chunks keep counting, only their entity is forgotten
+#[derive(Default)]
+pub struct SpawnCounts {
+    current: HashMap<ItemType, u32>,
+    spawned: HashMap<Entity, ItemType>,
+}
+
+impl SpawnCounts {
+    pub fn current(&self, item: ItemType) -> u32 {
+        self.current.get(&item).copied().unwrap_or(0)
+    }
+
+    fn add(&mut self, ent: Entity, item: ItemType) {
+        self.spawned.insert(ent, item);
+        *self.current.entry(item).or_insert(0) += 1;
+    }
+
+    /// Counts a spawn that has no entity, for loading a save
+    pub fn count_put_away(&mut self, item: ItemType) {
+        *self.current.entry(item).or_insert(0) += 1;
+    }
+
+    /// Forgets the entity of a spawn whose chunk is stored without uncounting it
+    pub fn put_away(&mut self, ent: Entity) {
+        self.spawned.remove(&ent);
+    }
+
+    /// Follows a counted spawn again once it is back in the world
+    pub fn bring_back(&mut self, ent: Entity, item: ItemType) {
+        self.spawned.insert(ent, item);
+    }
+
+    fn remove(&mut self, ent: Entity) {
+        if let Some(item) = self.spawned.remove(&ent) {
+            if let Some(count) = self.current.get_mut(&item) {
+                *count = count.saturating_sub(1);
+            }
+        }
     }
 }
 
-fn get_item(mut commands: Commands, graphics: Res<PlaceHolderGraphics>, mut time: Res<Time>) {
-    let flint = ItemData {
-        types: ItemType::Flint,
-        name: "Flint".to_string(),
-        graphics: graphics.flint_index,
-        pickupable: true,
+/// Marks items placed by natural spawning, so they stop counting once picked up
+#[derive(Component)]
+pub struct NaturalSpawn;
 
-        current_num: 0,
-        max_num: 100,
-    };
-    commands.insert_resource(Items { flint: flint });
-    let mut timer = Timer::new(Duration::from_secs(1), true);
-    timer.tick(time.delta());
+impl Plugin for ItemPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(load_item_registry(ITEMS_PATH))
+            .init_resource::<SpawnCounts>()
+            .add_system(natural_spawn)
+            .add_system(update_object_sprites)
+            //Despawns are only seen once the commands of the update stage have run
+            .add_system_to_stage(CoreStage::PostUpdate, forget_despawned_spawns);
+    }
 }
 
 pub fn spawn_item(
-    item: &ItemData,
+    item: ItemType,
     position: Vec2,
-    sprite: TextureAtlasSprite,
     commands: &mut Commands,
-    graphics: &Res<PlaceHolderGraphics>,
+    graphics: &PlaceHolderGraphics,
+    registry: &ItemRegistry,
 ) -> Entity {
     let mut sprite = commands.spawn_bundle(SpriteSheetBundle {
-        sprite: sprite,
+        sprite: graphics.item_sprite(item),
         texture_atlas: graphics.texture_atlas.clone(),
         transform: Transform {
             translation: position.extend(0.0),
@@ -92,23 +165,76 @@ pub fn spawn_item(
         ..Default::default()
     });
 
-    match item.pickupable {
-        true => sprite.insert(Pickupable { item: item.types }),
-        false => sprite.insert(Object { item: item.types }),
+    match registry.get(item).is_some_and(|desc| desc.pickupable) {
+        true => sprite.insert(Pickupable {
+            item: item,
+            count: 1,
+        }),
+        false => sprite.insert(Object {
+            object: WorldObject::Item(item),
+        }),
     };
 
-    sprite.insert(Name::new(item.name.clone())).id()
+    sprite.insert(Name::new(registry.name(item))).id()
 }
 
-pub fn dropped_item(
-    mut commands: Commands,
+/// Places a world object such as a crafted campfire, the sprite is a child
+/// so it can be offset to sit on its anchor
+pub fn spawn_object(
+    object: WorldObject,
+    position: Vec2,
+    commands: &mut Commands,
+    graphics: &PlaceHolderGraphics,
+) -> Entity {
+    let (sprite, offset) = graphics.object_sprite(object);
+    let graphic = commands
+        .spawn_bundle(SpriteSheetBundle {
+            sprite: sprite,
+            texture_atlas: graphics.texture_atlas.clone(),
+            transform: Transform::from_translation(offset),
+            ..Default::default()
+        })
+        .insert(ObjectSprite)
+        .id();
+    commands
+        .spawn_bundle((
+            Transform::from_translation(position.extend(0.0)),
+            GlobalTransform::default(),
+        ))
+        .insert(Object { object: object })
+        .insert(Name::new(format!("{:?}", object)))
+        .add_child(graphic)
+        .id()
+}
+
+/// Keeps an object's sprite in sync when it turns into another object, like a harvested tree
+fn update_object_sprites(
     graphics: Res<PlaceHolderGraphics>,
-    item: &ItemData,
+    object_query: Query<(&Object, &Children), Changed<Object>>,
+    mut sprite_query: Query<(&mut TextureAtlasSprite, &mut Transform), With<ObjectSprite>>,
+) {
+    for (object, children) in object_query.iter() {
+        for child in children.iter() {
+            if let Ok((mut sprite, mut transform)) = sprite_query.get_mut(*child) {
+                let (new_sprite, offset) = graphics.object_sprite(object.object);
+                *sprite = new_sprite;
+                transform.translation = offset;
+            }
+        }
+    }
+}
+
+/// Spawns a stack that always can be picked up again, whatever the registry says
+pub fn dropped_item(
+    item: ItemType,
+    count: usize,
     position: Vec2,
-    sprite: TextureAtlasSprite,
+    commands: &mut Commands,
+    graphics: &PlaceHolderGraphics,
+    registry: &ItemRegistry,
 ) -> Entity {
     let mut sprite = commands.spawn_bundle(SpriteSheetBundle {
-        sprite: sprite,
+        sprite: graphics.item_sprite(item),
         texture_atlas: graphics.texture_atlas.clone(),
         transform: Transform {
             translation: position.extend(0.0),
@@ -116,39 +242,96 @@ pub fn dropped_item(
         },
         ..Default::default()
     });
-    sprite.insert(Pickupable { item: item.types }).insert(Name::new(item.name.clone())).id()
+    sprite
+        .insert(Pickupable {
+            item: item,
+            count: count,
+        })
+        .insert(Name::new(registry.name(item)))
+        .id()
 }
 
-fn natural_spawn_flint(
+/// Rolls every item's spawn rule, scaled by the frame time so the frame rate doesn't matter
+fn natural_spawn(
     mut commands: Commands,
+    time: Res<Time>,
     graphics: Res<PlaceHolderGraphics>,
-    mut items: ResMut<Items>,
+    registry: Res<ItemRegistry>,
+    gen: Res<WorldGen>,
+    mut rng: ResMut<GameRng>,
+    mut counts: ResMut<SpawnCounts>,
     player_query: Query<&Transform, With<Player>>,
-    mut time: Res<Time>,
+    camera_query: Query<&Transform, With<GameCamera>>,
+    pickupable_query: Query<(&Transform, &Pickupable)>,
 ) {
-    if items.flint.current_num < items.flint.max_num {
-        let mut rng = thread_rng();
-        let spawn = rng.gen_range(0..=100);
-        println!("{}", spawn);
-        if spawn <= 5{
-            let player_transform = player_query.single().translation;
-            let x_add: f32 = rng.gen_range(-467.0..=467.0);
-            let y_add: f32 = rng.gen_range(-380.0..=380.0);
-            let position = Vec2::new(player_transform.x + x_add, player_transform.y + y_add);
-
-            let mut sprite = TextureAtlasSprite::new(
-                *graphics
-                    .item_map
-                    .get(&items.flint.types)
-                    .expect("No graphic for item"),
-            );
-            sprite.custom_size = Some(Vec2::splat(25.0));
-            spawn_item(&items.flint, position, sprite, &mut commands, &graphics);
-            items.flint.current_num += 1;
+    let player_pos = player_query.single().translation.truncate();
+    let camera_pos = camera_query.single().translation.truncate();
+    for desc in registry.iter() {
+        let rule = match &desc.natural_spawn {
+            Some(rule) => rule,
+            None => continue,
+        };
+        if counts.current(desc.id) >= rule.max_alive {
+            continue;
+        }
+        let chance = (rule.per_second * time.delta_seconds()).min(1.0);
+        if !rng.gen_bool(chance as f64) {
+            continue;
+        }
+        let position = match spawn_spot(rule, player_pos, camera_pos, &mut rng) {
+            Some(position) => position,
+            None => continue,
+        };
+
+        let chunk = gen.chunk_of(position);
+        let biome = gen.biome_at(rng.seed(), chunk);
+        if !rule.biomes.contains(&biome) {
+            continue;
+        }
+        if let Some(cap) = gen.biome(biome).cap(desc.id) {
+            let in_chunk = pickupable_query
+                .iter()
+                .filter(|(transform, pickupable)| {
+                    pickupable.item == desc.id
+                        && gen.chunk_of(transform.translation.truncate()) == chunk
+                })
+                .count() as u32;
+            if in_chunk >= cap {
+                continue;
+            }
         }
+        let ent = spawn_item(desc.id, position, &mut commands, &graphics, &registry);
+        commands.entity(ent).insert(NaturalSpawn);
+        counts.add(ent, desc.id);
     }
 }
 
-fn spawner() {
-    natural_spawn_flint;
+/// A few tries at a spot far enough from the player and off screen if the rule wants it
+fn spawn_spot(
+    rule: &SpawnRule,
+    player_pos: Vec2,
+    camera_pos: Vec2,
+    rng: &mut GameRng,
+) -> Option<Vec2> {
+    let half_screen = Vec2::new(HEIGHT * RESOLUTION, HEIGHT) / 2.0;
+    for _ in 0..SPAWN_SPOT_TRIES {
+        let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+        let distance = rng.gen_range(rule.min_distance..MAX_SPAWN_DISTANCE);
+        let spot = player_pos + Vec2::new(angle.cos(), angle.sin()) * distance;
+        let offset = (spot - camera_pos).abs();
+        let on_screen = offset.x < half_screen.x && offset.y < half_screen.y;
+        if !(rule.off_screen && on_screen) {
+            return Some(spot);
+        }
+    }
+    None
+}
+
+fn forget_despawned_spawns(
+    removed: RemovedComponents<NaturalSpawn>,
+    mut counts: ResMut<SpawnCounts>,
+) {
+    for ent in removed.iter() {
+        counts.remove(ent);
+    }
 }
@@ -0,0 +1,106 @@
+#![allow(clippy::redundant_field_names)]
+#![allow(clippy::type_complexity)]
+#![allow(clippy::too_many_arguments)]
+use std::fs;
+
+use bevy::{app::PluginGroupBuilder, asset::FileAssetIo, prelude::*, transform::TransformPlugin};
+use bevy_inspector_egui::InspectableRegistry;
+
+pub const HEIGHT: f32 = 900.0;
+pub const RESOLUTION: f32 = 16.0 / 9.0;
+/// Folder the `AssetServer` loads from, data files are read from it too
+pub const ASSET_FOLDER: &str = "assets";
+
+#[derive(Component)]
+pub struct GameCamera;
+
+pub mod chest;
+pub mod clock;
+pub mod crafting;
+pub mod fire;
+pub mod graphics;
+pub mod harvest;
+pub mod inventory;
+pub mod items;
+pub mod player;
+pub mod registry;
+pub mod rng;
+pub mod save;
+pub mod survival;
+pub mod worldgen;
+
+use graphics::PlaceHolderGraphics;
+
+/// Everything that makes up the game itself, none of it needs a window or GPU
+pub struct GamePlugins;
+
+impl PluginGroup for GamePlugins {
+    fn build(&mut self, group: &mut PluginGroupBuilder) {
+        group
+            .add(rng::RngPlugin)
+            .add(inventory::InventoryPlugin)
+            .add(chest::ChestPlugin)
+            .add(player::PlayerPlugin)
+            .add(items::ItemPlugin)
+            .add(crafting::CraftingPlugin)
+            .add(harvest::HarvestPlugin)
+            .add(clock::ClockPlugin)
+            .add(fire::FirePlugin)
+            .add(survival::SurvivalPlugin)
+            .add(worldgen::WorldGenPlugin)
+            .add(save::SavePlugin);
+    }
+}
+
+/// Sprite sheet, fonts, UI and the fire overlay, all needing a window to show anything
+pub struct PresentationPlugins;
+
+impl PluginGroup for PresentationPlugins {
+    fn build(&mut self, group: &mut PluginGroupBuilder) {
+        group
+            .add(graphics::GraphicsPlugin)
+            .add(inventory::InventoryUiPlugin)
+            .add(chest::ChestUiPlugin)
+            .add(crafting::CraftingUiPlugin)
+            .add(clock::ClockUiPlugin)
+            .add(fire::FireOverlayPlugin)
+            .add(survival::SurvivalUiPlugin);
+    }
+}
+
+/// Stands in for the window, input and graphics so `GamePlugins` can run on top of
+/// `MinimalPlugins`, input is pressed by hand on the `Input` resources.
+/// Has to be added before `GamePlugins`
+pub struct HeadlessPlugin;
+
+impl Plugin for HeadlessPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<InspectableRegistry>()
+            .init_resource::<Input<KeyCode>>()
+            .init_resource::<Input<MouseButton>>()
+            .insert_resource(PlaceHolderGraphics::headless())
+            .add_plugin(TransformPlugin)
+            .add_startup_system_to_stage(StartupStage::PreStartup, spawn_camera);
+    }
+}
+
+/// Reads a data file from the assets folder, found the same way the `AssetServer` finds
+/// textures so the game doesn't depend on the working directory. Data files are read right away
+/// instead of going through an asset loader, the plugins need them while they are being built
+pub fn read_asset(path: &str) -> String {
+    let full_path = FileAssetIo::get_root_path().join(ASSET_FOLDER).join(path);
+    fs::read_to_string(&full_path)
+        .unwrap_or_else(|err| panic!("Failed to read {}: {}", full_path.display(), err))
+}
+
+pub fn spawn_camera(mut commands: Commands) {
+    commands.spawn_bundle(UiCameraBundle::default());
+    let mut camera = OrthographicCameraBundle::new_2d();
+
+    camera.orthographic_projection.left = -RESOLUTION;
+    camera.orthographic_projection.right = 1.0 * RESOLUTION;
+    camera.orthographic_projection.top = 1.0;
+    camera.orthographic_projection.bottom = -1.0;
+
+    commands.spawn_bundle(camera).insert(GameCamera);
+}
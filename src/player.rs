@@ -1,9 +1,21 @@
 use bevy::prelude::*;
 use bevy_inspector_egui::Inspectable;
+use serde::{Deserialize, Serialize};
 
-use crate::{graphics::PlaceHolderGraphics, inventory::Inventory, GameCamera};
+use crate::{
+    crafting::KnownRecipes,
+    graphics::PlaceHolderGraphics,
+    inventory::{Hand, Inventory},
+    survival::{Health, Hunger, Warmth},
+    GameCamera,
+};
 
-#[derive(Component, Inspectable)]
+/// Used for everything done to the world around the player, like harvesting
+pub const INTERACT_KEY: KeyCode = KeyCode::F;
+/// Uses the selected item, like eating it
+pub const USE_KEY: KeyCode = KeyCode::R;
+
+#[derive(Component, Inspectable, Clone, Serialize, Deserialize)]
 pub struct Player {
     pub speed: f32,
     pub arm_length: f32,
@@ -37,6 +49,11 @@ fn spawn_player(mut commands: Commands, graphics: Res<PlaceHolderGraphics>) {
             arm_length: 50.0,
         })
         .insert(Inventory::default())
+        .insert(Hand::default())
+        .insert(KnownRecipes::default())
+        .insert(Health::default())
+        .insert(Hunger::default())
+        .insert(Warmth::default())
         .insert(Name::new("Player"));
 }
 
@@ -0,0 +1,172 @@
+use bevy::{prelude::*, utils::HashMap};
+use serde::Deserialize;
+
+use crate::{
+    items::{ItemType, MAX_SPAWN_DISTANCE},
+    read_asset,
+    worldgen::Biome,
+};
+
+pub const ITEMS_PATH: &str = "items.ron";
+
+/// Everything the game knows about one kind of item
+#[derive(Clone, Deserialize)]
+pub struct ItemDesc {
+    pub id: ItemType,
+    pub name: String,
+    pub stack_size: usize,
+    #[serde(default)]
+    pub pickupable: bool,
+    /// Uses before a tool breaks, only tools can have it
+    #[serde(default)]
+    pub durability: Option<u32>,
+    /// Seconds a campfire keeps burning when this is added to it
+    #[serde(default)]
+    pub fuel: f32,
+    /// Set for things that can be eaten
+    #[serde(default)]
+    pub consumable: Option<Consumable>,
+    /// Set for things that keep turning up in the world by themselves
+    #[serde(default)]
+    pub natural_spawn: Option<SpawnRule>,
+    /// Backpack slots added when used, for bags
+    #[serde(default)]
+    pub backpack_slots: usize,
+}
+
+/// How an item turns up around the player on its own
+#[derive(Clone, Deserialize)]
+pub struct SpawnRule {
+    /// Average spawns each second while below max_alive
+    pub per_second: f32,
+    /// Most naturally spawned ones not yet picked up, stored chunks included
+    pub max_alive: u32,
+    /// Pixels from the player, spawns are always within MAX_SPAWN_DISTANCE
+    pub min_distance: f32,
+    /// Only spawn where the player can't see it appear
+    #[serde(default)]
+    pub off_screen: bool,
+    pub biomes: Vec<Biome>,
+}
+
+/// What eating one of an item does
+#[derive(Clone, Copy, Deserialize)]
+pub struct Consumable {
+    /// Hunger restored
+    pub nutrition: f32,
+    #[serde(default)]
+    pub health: f32,
+}
+
+#[derive(Deserialize)]
+struct ItemFile {
+    items: Vec<ItemDesc>,
+}
+
+pub struct ItemRegistry {
+    items: HashMap<ItemType, ItemDesc>,
+    /// Ids in file order, so going through the items is the same on every run
+    order: Vec<ItemType>,
+}
+
+impl ItemRegistry {
+    pub fn get(&self, item: ItemType) -> Option<&ItemDesc> {
+        self.items.get(&item)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ItemDesc> {
+        self.order.iter().filter_map(|id| self.items.get(id))
+    }
+
+    /// Most of an item one inventory slot holds
+    pub fn stack_size(&self, item: ItemType) -> usize {
+        self.get(item).map_or(1, |desc| desc.stack_size)
+    }
+
+    /// Uses a tool survives, None means it never breaks
+    pub fn durability(&self, item: ItemType) -> Option<u32> {
+        self.get(item).and_then(|desc| desc.durability)
+    }
+
+    /// Burn time an item adds to a campfire, 0 for things that don't burn
+    pub fn fuel(&self, item: ItemType) -> f32 {
+        self.get(item).map_or(0.0, |desc| desc.fuel)
+    }
+
+    pub fn consumable(&self, item: ItemType) -> Option<Consumable> {
+        self.get(item).and_then(|desc| desc.consumable)
+    }
+
+    /// Backpack slots an item adds when used, 0 for anything that isn't a bag
+    pub fn backpack_slots(&self, item: ItemType) -> usize {
+        self.get(item).map_or(0, |desc| desc.backpack_slots)
+    }
+
+    /// Display name of an item, falls back to the id for unregistered items
+    pub fn name(&self, item: ItemType) -> String {
+        match self.get(item) {
+            Some(desc) => desc.name.clone(),
+            None => format!("{:?}", item),
+        }
+    }
+}
+
+/// Reads the item definitions from disk so items can be added or tuned without recompiling
+pub fn load_item_registry(path: &str) -> ItemRegistry {
+    let contents = read_asset(path);
+    let registry = parse_item_registry(&contents)
+        .unwrap_or_else(|err| panic!("Invalid item file {}: {}", path, err));
+    info!("Loaded {} items from {}", registry.items.len(), path);
+    registry
+}
+
+pub fn parse_item_registry(contents: &str) -> Result<ItemRegistry, String> {
+    let file: ItemFile = ron::de::from_str(contents).map_err(|err| err.to_string())?;
+    let mut items = HashMap::default();
+    let mut order = Vec::new();
+    for desc in file.items {
+        if matches!(desc.id, ItemType::None | ItemType::Default) {
+            return Err(format!("{:?} is reserved and can't be registered", desc.id));
+        }
+        if desc.stack_size == 0 {
+            return Err(format!("{:?} has a stack size of 0", desc.id));
+        }
+        if desc.fuel < 0.0 {
+            return Err(format!("{:?} has negative fuel", desc.id));
+        }
+        if let Some(consumable) = desc.consumable {
+            if consumable.nutrition < 0.0 || consumable.health < 0.0 {
+                return Err(format!("{:?} is harmful to eat", desc.id));
+            }
+        }
+        match desc.durability {
+            Some(0) => return Err(format!("{:?} has a durability of 0", desc.id)),
+            Some(_) if desc.id.tool().is_none() => {
+                return Err(format!("{:?} isn't a tool but has durability", desc.id))
+            }
+            Some(_) if desc.stack_size != 1 => {
+                return Err(format!("{:?} has durability so it can't stack", desc.id))
+            }
+            _ => {}
+        }
+        if let Some(rule) = &desc.natural_spawn {
+            if rule.per_second < 0.0 {
+                return Err(format!("{:?} has a negative spawn rate", desc.id));
+            }
+            if !(0.0..MAX_SPAWN_DISTANCE).contains(&rule.min_distance) {
+                return Err(format!(
+                    "{:?} has to spawn closer than {} pixels",
+                    desc.id, MAX_SPAWN_DISTANCE
+                ));
+            }
+        }
+        order.push(desc.id);
+        if let Some(previous) = items.insert(desc.id, desc) {
+            return Err(format!("{:?} is registered twice", previous.id));
+        }
+    }
+    Ok(ItemRegistry {
+        items: items,
+        order: order,
+    })
+}
@@ -0,0 +1,77 @@
+use bevy::prelude::*;
+use rand::{rngs::StdRng, Error, RngCore, SeedableRng};
+
+/// Passed on launch as `--seed <number>` to replay a world
+pub const SEED_ARG: &str = "--seed";
+
+/// The only source of randomness for spawning and generation, the same seed
+/// and inputs give the same world
+pub struct GameRng {
+    seed: u64,
+    rng: StdRng,
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        GameRng {
+            seed: seed,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// A fresh random seed, logged so the run can be replayed
+    pub fn from_entropy() -> Self {
+        GameRng::new(rand::random())
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+impl RngCore for GameRng {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.rng.try_fill_bytes(dest)
+    }
+}
+
+/// Reads the seed given after `SEED_ARG`, `None` when there is none
+pub fn parse_seed_arg(args: &[String]) -> Result<Option<u64>, String> {
+    match args.iter().position(|arg| arg == SEED_ARG) {
+        None => Ok(None),
+        Some(i) => {
+            let value = args
+                .get(i + 1)
+                .ok_or_else(|| format!("{} needs a number after it", SEED_ARG))?;
+            value
+                .parse()
+                .map(Some)
+                .map_err(|_| format!("{} isn't a valid seed", value))
+        }
+    }
+}
+
+/// Uses a `GameRng` inserted before it, or seeds one randomly
+pub struct RngPlugin;
+
+impl Plugin for RngPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.world.contains_resource::<GameRng>() {
+            app.insert_resource(GameRng::from_entropy());
+        }
+        let seed = app.world.get_resource::<GameRng>().unwrap().seed();
+        info!("World seed: {}", seed);
+    }
+}
@@ -0,0 +1,458 @@
+use std::fs;
+
+use bevy::{ecs::system::CommandQueue, prelude::*, utils::HashSet};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    chest::{ChestMenu, CHEST_SIZE},
+    clock::WorldClock,
+    crafting::{CraftingJob, KnownRecipes},
+    fire::Campfire,
+    graphics::PlaceHolderGraphics,
+    inventory::{Hand, Inventory, HOTBAR_SIZE, MAX_CAPACITY},
+    items::{
+        dropped_item, spawn_item, spawn_object, ItemType, NaturalSpawn, Object, Pickupable,
+        SpawnCounts, WorldObject,
+    },
+    player::Player,
+    registry::ItemRegistry,
+    rng::GameRng,
+    survival::{Health, Hunger, Warmth},
+    worldgen::{ChunkMap, Ground, StoredChunk},
+};
+
+pub const SAVE_PATH: &str = "save.ron";
+/// Bumped whenever the layout of `SaveGame` changes so old saves are refused instead of misread
+pub const SAVE_VERSION: u32 = 1;
+pub const SAVE_KEY: KeyCode = KeyCode::F5;
+pub const LOAD_KEY: KeyCode = KeyCode::F9;
+
+/// Everything needed to rebuild a game, positions are (x, y) in world pixels
+#[derive(Serialize, Deserialize)]
+pub struct SaveGame {
+    pub version: u32,
+    /// Loading reseeds the `GameRng` with it, so a loaded game stays reproducible
+    pub seed: u64,
+    pub player: PlayerSave,
+    pub pickupables: Vec<PickupableSave>,
+    pub objects: Vec<ObjectSave>,
+    pub clock: ClockSave,
+    /// Chunks that are in the world, their contents are in pickupables and objects
+    pub loaded_chunks: Vec<(i32, i32)>,
+    /// Chunks the player has been to but are put away
+    pub stored_chunks: Vec<ChunkSave>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PlayerSave {
+    pub position: (f32, f32),
+    pub player: Player,
+    pub inventory: Inventory,
+    pub known_recipes: Vec<WorldObject>,
+    pub health: Health,
+    pub hunger: Hunger,
+    pub warmth: Warmth,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PickupableSave {
+    pub item: ItemType,
+    pub count: usize,
+    pub position: (f32, f32),
+    /// Still counts against its spawn rule's max_alive
+    #[serde(default)]
+    pub natural: bool,
+}
+
+/// Plants that were regrowing start their timer over after loading
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ObjectSave {
+    pub object: WorldObject,
+    pub position: (f32, f32),
+    #[serde(default)]
+    pub fuel: Option<f32>,
+    /// What a chest holds
+    #[serde(default)]
+    pub inventory: Option<Inventory>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ClockSave {
+    pub time: f32,
+    pub day: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ChunkSave {
+    pub chunk: (i32, i32),
+    pub pickupables: Vec<PickupableSave>,
+    pub objects: Vec<ObjectSave>,
+}
+
+/// Only the version, read first so an outdated save gets a clear error
+#[derive(Deserialize)]
+struct SaveHeader {
+    version: u32,
+}
+
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(save_and_load_keys.exclusive_system());
+    }
+}
+
+fn save_and_load_keys(world: &mut World) {
+    let keyboard = world.get_resource::<Input<KeyCode>>().unwrap();
+    let save = keyboard.just_pressed(SAVE_KEY);
+    let load = keyboard.just_pressed(LOAD_KEY);
+    if save {
+        match save_game(world).and_then(|save| write_save(SAVE_PATH, &save)) {
+            Ok(()) => info!("Saved the game to {}", SAVE_PATH),
+            Err(err) => warn!("Couldn't save: {}", err),
+        }
+    } else if load {
+        let registry = world.get_resource::<ItemRegistry>().unwrap();
+        match read_save(SAVE_PATH, registry).and_then(|save| load_game(world, save)) {
+            Ok(()) => info!("Loaded the game from {}", SAVE_PATH),
+            Err(err) => warn!("Couldn't load: {}", err),
+        }
+    }
+}
+
+pub fn write_save(path: &str, save: &SaveGame) -> Result<(), String> {
+    let contents = to_save_string(save)?;
+    fs::write(path, contents).map_err(|err| format!("Failed to write {}: {}", path, err))
+}
+
+pub fn read_save(path: &str, registry: &ItemRegistry) -> Result<SaveGame, String> {
+    let contents =
+        fs::read_to_string(path).map_err(|err| format!("Failed to read {}: {}", path, err))?;
+    parse_save(&contents, registry)
+}
+
+pub fn to_save_string(save: &SaveGame) -> Result<String, String> {
+    ron::ser::to_string_pretty(save, ron::ser::PrettyConfig::default())
+        .map_err(|err| err.to_string())
+}
+
+/// Refuses saves whose inventories couldn't have come out of play
+pub fn parse_save(contents: &str, registry: &ItemRegistry) -> Result<SaveGame, String> {
+    let header: SaveHeader = ron::de::from_str(contents).map_err(|err| err.to_string())?;
+    if header.version != SAVE_VERSION {
+        return Err(format!(
+            "save is version {} but this game reads version {}",
+            header.version, SAVE_VERSION
+        ));
+    }
+    let save: SaveGame = ron::de::from_str(contents).map_err(|err| err.to_string())?;
+    let inventory = &save.player.inventory;
+    if !(HOTBAR_SIZE..=MAX_CAPACITY).contains(&inventory.capacity()) {
+        return Err(format!(
+            "an inventory of {} slots isn't possible",
+            inventory.capacity()
+        ));
+    }
+    if inventory.selected_slot() >= HOTBAR_SIZE {
+        return Err("the selected inventory slot doesn't exist".to_string());
+    }
+    check_stacks(inventory, registry)?;
+    if !(0.0..1.0).contains(&save.clock.time) {
+        return Err(format!("{} isn't a time of day", save.clock.time));
+    }
+
+    let stored = save
+        .stored_chunks
+        .iter()
+        .flat_map(|chunk| chunk.pickupables.iter());
+    for pickupable in save.pickupables.iter().chain(stored) {
+        check_stack(pickupable.item, pickupable.count, registry)?;
+    }
+    let stored = save
+        .stored_chunks
+        .iter()
+        .flat_map(|chunk| chunk.objects.iter());
+    for object in save.objects.iter().chain(stored) {
+        if let Some(fuel) = object.fuel {
+            if !fuel.is_finite() || fuel < 0.0 {
+                return Err(format!("a campfire can't have {} fuel", fuel));
+            }
+        }
+        let inventory = match &object.inventory {
+            Some(inventory) => inventory,
+            None => continue,
+        };
+        if object.object != WorldObject::Chest || inventory.capacity() != CHEST_SIZE {
+            return Err(format!(
+                "a {:?} can't hold an inventory of {} slots",
+                object.object,
+                inventory.capacity()
+            ));
+        }
+        check_stacks(inventory, registry)?;
+    }
+    Ok(save)
+}
+
+/// Every stack in an inventory has to be valid, empty slots aside
+fn check_stacks(inventory: &Inventory, registry: &ItemRegistry) -> Result<(), String> {
+    for entry in inventory.items.iter().filter(|entry| entry.count > 0) {
+        check_stack(entry.item, entry.count, registry)?;
+    }
+    Ok(())
+}
+
+/// A stack has to be a known item and hold from one to a full stack of it
+fn check_stack(item: ItemType, count: usize, registry: &ItemRegistry) -> Result<(), String> {
+    if registry.get(item).is_none() {
+        return Err(format!("{:?} isn't a known item", item));
+    }
+    if !(1..=registry.stack_size(item)).contains(&count) {
+        return Err(format!(
+            "a stack of {} {:?} isn't between 1 and the stack size of {}",
+            count,
+            item,
+            registry.stack_size(item)
+        ));
+    }
+    Ok(())
+}
+
+/// Snapshots the world, refuses while a craft is running since its ingredients
+/// are out of the inventory and the result doesn't exist yet
+pub fn save_game(world: &mut World) -> Result<SaveGame, String> {
+    let mut player_query = world.query_filtered::<(
+        &Transform,
+        &Player,
+        &Inventory,
+        &KnownRecipes,
+        &Health,
+        &Hunger,
+        &Warmth,
+        Option<&CraftingJob>,
+        Option<&Hand>,
+    ), With<Player>>();
+    let (transform, player, inventory, known, health, hunger, warmth, job, hand) = player_query
+        .iter(world)
+        .next()
+        .ok_or_else(|| "there is no player".to_string())?;
+    if job.is_some() {
+        return Err("wait for the current craft to finish".to_string());
+    }
+    if hand.is_some_and(|hand| hand.held.count > 0) {
+        return Err("put down the stack you are holding first".to_string());
+    }
+    let player = PlayerSave {
+        position: (transform.translation.x, transform.translation.y),
+        player: player.clone(),
+        inventory: inventory.clone(),
+        known_recipes: known.known.iter().copied().collect(),
+        health: health.clone(),
+        hunger: hunger.clone(),
+        warmth: warmth.clone(),
+    };
+
+    let mut pickupable_query = world.query::<(&Transform, &Pickupable, Option<&NaturalSpawn>)>();
+    let pickupables = pickupable_query
+        .iter(world)
+        .map(|(transform, pickupable, natural)| PickupableSave {
+            item: pickupable.item,
+            count: pickupable.count,
+            position: (transform.translation.x, transform.translation.y),
+            natural: natural.is_some(),
+        })
+        .collect();
+
+    let mut object_query =
+        world.query::<(&Transform, &Object, Option<&Campfire>, Option<&Inventory>)>();
+    let objects = object_query
+        .iter(world)
+        .map(|(transform, object, campfire, inventory)| ObjectSave {
+            object: object.object,
+            position: (transform.translation.x, transform.translation.y),
+            fuel: campfire.map(|campfire| campfire.fuel),
+            inventory: inventory.cloned(),
+        })
+        .collect();
+
+    let clock = world
+        .get_resource::<WorldClock>()
+        .map(|clock| ClockSave {
+            time: clock.time,
+            day: clock.day,
+        })
+        .ok_or_else(|| "there is no world clock".to_string())?;
+    let seed = world
+        .get_resource::<GameRng>()
+        .map(|rng| rng.seed())
+        .ok_or_else(|| "there is no world seed".to_string())?;
+
+    let chunks = world
+        .get_resource::<ChunkMap>()
+        .ok_or_else(|| "there is no chunk map".to_string())?;
+    let loaded_chunks = chunks
+        .loaded
+        .iter()
+        .map(|chunk| (chunk.x, chunk.y))
+        .collect();
+    let stored_chunks = chunks
+        .stored
+        .iter()
+        .map(|(chunk, stored)| ChunkSave {
+            chunk: (chunk.x, chunk.y),
+            pickupables: stored.pickupables.clone(),
+            objects: stored.objects.clone(),
+        })
+        .collect();
+
+    Ok(SaveGame {
+        version: SAVE_VERSION,
+        seed: seed,
+        player: player,
+        pickupables: pickupables,
+        objects: objects,
+        clock: clock,
+        loaded_chunks: loaded_chunks,
+        stored_chunks: stored_chunks,
+    })
+}
+
+/// Replaces the current world with a save, items and objects are spawned the
+/// same way as during play so their sprites are rebuilt from the current sheet
+pub fn load_game(world: &mut World, save: SaveGame) -> Result<(), String> {
+    let mut player_query = world.query_filtered::<Entity, With<Player>>();
+    let player_ent = player_query
+        .iter(world)
+        .next()
+        .ok_or_else(|| "there is no player".to_string())?;
+
+    let mut old_query =
+        world.query_filtered::<Entity, Or<(With<Pickupable>, With<Object>, With<Ground>)>>();
+    let old: Vec<Entity> = old_query.iter(world).collect();
+    let job_bar = world.get::<CraftingJob>(player_ent).map(|job| job.bar());
+
+    //Counted afresh from the save, the entities being replaced are forgotten with the old counts
+    let mut counts = SpawnCounts::default();
+    let stored = save
+        .stored_chunks
+        .iter()
+        .flat_map(|chunk| chunk.pickupables.iter());
+    for pickupable in save.pickupables.iter().chain(stored) {
+        if pickupable.natural {
+            counts.count_put_away(pickupable.item);
+        }
+    }
+
+    let mut queue = CommandQueue::default();
+    {
+        let graphics = world
+            .get_resource::<PlaceHolderGraphics>()
+            .ok_or_else(|| "graphics aren't loaded".to_string())?;
+        let registry = world
+            .get_resource::<ItemRegistry>()
+            .ok_or_else(|| "items aren't loaded".to_string())?;
+        let mut commands = Commands::new(&mut queue, world);
+
+        for ent in old {
+            commands.entity(ent).despawn_recursive();
+        }
+        //Whatever was being crafted belongs to the world being replaced
+        if let Some(bar) = job_bar {
+            commands.entity(bar).despawn_recursive();
+            commands.entity(player_ent).remove::<CraftingJob>();
+        }
+
+        spawn_saved(
+            &save.pickupables,
+            &save.objects,
+            &mut commands,
+            graphics,
+            registry,
+            &mut counts,
+        );
+    }
+    queue.apply(world);
+    world.insert_resource(counts);
+
+    let player = save.player;
+    let mut player_ent = world.entity_mut(player_ent);
+    if let Some(mut transform) = player_ent.get_mut::<Transform>() {
+        transform.translation.x = player.position.0;
+        transform.translation.y = player.position.1;
+    }
+    player_ent.insert(player.player);
+    player_ent.insert(player.inventory);
+    player_ent.insert(Hand::default());
+    player_ent.insert(KnownRecipes {
+        known: player.known_recipes.into_iter().collect::<HashSet<_>>(),
+    });
+    player_ent.insert(player.health);
+    player_ent.insert(player.hunger);
+    player_ent.insert(player.warmth);
+
+    if let Some(mut clock) = world.get_resource_mut::<WorldClock>() {
+        clock.set_time(save.clock.time, save.clock.day);
+    }
+    world.insert_resource(GameRng::new(save.seed));
+    world.insert_resource(ChestMenu::default());
+    world.insert_resource(ChunkMap {
+        loaded: save
+            .loaded_chunks
+            .into_iter()
+            .map(|(x, y)| IVec2::new(x, y))
+            .collect(),
+        stored: save
+            .stored_chunks
+            .into_iter()
+            .map(|chunk| {
+                let stored = StoredChunk {
+                    pickupables: chunk.pickupables,
+                    objects: chunk.objects,
+                };
+                (IVec2::new(chunk.chunk.0, chunk.chunk.1), stored)
+            })
+            .collect(),
+    });
+    Ok(())
+}
+
+/// Spawns saved items and objects the same way as during play, natural spawns
+/// must already be counted in `counts`
+pub fn spawn_saved(
+    pickupables: &[PickupableSave],
+    objects: &[ObjectSave],
+    commands: &mut Commands,
+    graphics: &PlaceHolderGraphics,
+    registry: &ItemRegistry,
+    counts: &mut SpawnCounts,
+) {
+    for pickupable in pickupables.iter() {
+        let position = Vec2::new(pickupable.position.0, pickupable.position.1);
+        let ent = dropped_item(
+            pickupable.item,
+            pickupable.count,
+            position,
+            commands,
+            graphics,
+            registry,
+        );
+        if pickupable.natural {
+            commands.entity(ent).insert(NaturalSpawn);
+            counts.bring_back(ent, pickupable.item);
+        }
+    }
+    for saved in objects.iter() {
+        let position = Vec2::new(saved.position.0, saved.position.1);
+        let ent = match saved.object {
+            WorldObject::Item(item) => spawn_item(item, position, commands, graphics, registry),
+            object => spawn_object(object, position, commands, graphics),
+        };
+        if let Some(fuel) = saved.fuel {
+            commands.entity(ent).insert(Campfire { fuel: fuel });
+        }
+        if let Some(inventory) = &saved.inventory {
+            commands.entity(ent).insert(inventory.clone());
+        }
+    }
+}
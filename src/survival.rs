@@ -0,0 +1,328 @@
+use bevy::prelude::*;
+use bevy_inspector_egui::{Inspectable, RegisterInspectable};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clock::{DayPhase, WorldClock},
+    crafting::{CraftingBook, CraftingJob},
+    fire::Campfire,
+    graphics::PlaceHolderGraphics,
+    inventory::{take_selected_item, Hand, Inventory, InventoryEntry, InventoryEvents},
+    items::dropped_item,
+    player::{Player, USE_KEY},
+    registry::ItemRegistry,
+    GameCamera,
+};
+
+/// Per second
+const HUNGER_DECAY: f32 = 0.4;
+const STARVING_DAMAGE: f32 = 2.0;
+const FREEZING_DAMAGE: f32 = 3.0;
+/// Health recovered per second while both fed and warm
+const HEALTH_REGEN: f32 = 0.5;
+/// Fed and warm means above this fraction of the max
+const COMFORTABLE: f32 = 0.5;
+/// Warmth gained per second standing in a fire's light
+const FIRE_WARMTH: f32 = 5.0;
+const RESPAWN_POINT: Vec2 = Vec2::ZERO;
+
+const BAR_WIDTH: f32 = 200.0;
+const BAR_HEIGHT: f32 = 14.0;
+
+#[derive(Component, Inspectable, Clone, Serialize, Deserialize)]
+pub struct Health {
+    pub current: f32,
+    pub max: f32,
+}
+
+#[derive(Component, Inspectable, Clone, Serialize, Deserialize)]
+pub struct Hunger {
+    pub current: f32,
+    pub max: f32,
+}
+
+#[derive(Component, Inspectable, Clone, Serialize, Deserialize)]
+pub struct Warmth {
+    pub current: f32,
+    pub max: f32,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Health {
+            current: 100.0,
+            max: 100.0,
+        }
+    }
+}
+
+impl Default for Hunger {
+    fn default() -> Self {
+        Hunger {
+            current: 100.0,
+            max: 100.0,
+        }
+    }
+}
+
+impl Default for Warmth {
+    fn default() -> Self {
+        Warmth {
+            current: 100.0,
+            max: 100.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Stat {
+    Health,
+    Hunger,
+    Warmth,
+}
+
+#[derive(Component)]
+pub struct StatBar {
+    stat: Stat,
+}
+
+pub struct SurvivalPlugin;
+
+impl Plugin for SurvivalPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(get_hungry.label("stats"))
+            .add_system(change_warmth.label("stats").after("clock"))
+            .add_system(eat_selected.label("stats"))
+            .add_system(take_damage.label("damage").after("stats"))
+            //After the crafting jobs, so the move to the spawn point doesn't also refund the craft
+            .add_system(die_and_respawn.after("damage").after("craftingjobs"))
+            .register_inspectable::<Health>()
+            .register_inspectable::<Hunger>()
+            .register_inspectable::<Warmth>();
+    }
+}
+
+pub struct SurvivalUiPlugin;
+
+impl Plugin for SurvivalUiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(spawn_stat_bars)
+            .add_system(update_stat_bars.after("damage"));
+    }
+}
+
+/// How much warmth is lost per second in each part of the day
+fn cold(phase: DayPhase) -> f32 {
+    match phase {
+        DayPhase::Day => 0.1,
+        DayPhase::Dawn | DayPhase::Dusk => 0.4,
+        DayPhase::Night => 1.0,
+    }
+}
+
+fn get_hungry(time: Res<Time>, mut hunger_query: Query<&mut Hunger>) {
+    for mut hunger in hunger_query.iter_mut() {
+        hunger.current = (hunger.current - HUNGER_DECAY * time.delta_seconds()).max(0.0);
+    }
+}
+
+/// Standing in the light of a burning campfire warms up, the night cools down
+fn change_warmth(
+    time: Res<Time>,
+    clock: Res<WorldClock>,
+    campfire_query: Query<(&GlobalTransform, &Campfire)>,
+    mut warmth_query: Query<(&Transform, &mut Warmth)>,
+) {
+    for (transform, mut warmth) in warmth_query.iter_mut() {
+        let position = transform.translation.truncate();
+        let near_fire = campfire_query.iter().any(|(fire_transform, campfire)| {
+            campfire.is_lit()
+                && fire_transform.translation.truncate().distance(position)
+                    < campfire.light_radius()
+        });
+        let change = if near_fire {
+            FIRE_WARMTH
+        } else {
+            -cold(clock.phase())
+        };
+        warmth.current = (warmth.current + change * time.delta_seconds()).clamp(0.0, warmth.max);
+    }
+}
+
+/// Eats one of the selected item if it is food
+fn eat_selected(
+    keyboard: Res<Input<KeyCode>>,
+    registry: Res<ItemRegistry>,
+    mut events: InventoryEvents,
+    mut player_query: Query<(Entity, &mut Inventory, &mut Hunger, &mut Health), With<Player>>,
+) {
+    if !keyboard.just_pressed(USE_KEY) {
+        return;
+    }
+    let (player_ent, mut inventory, mut hunger, mut health) = player_query.single_mut();
+    let item = inventory.selected_item();
+    let consumable = match registry.consumable(item) {
+        Some(consumable) => consumable,
+        //Bags are used by the inventory instead
+        None if registry.backpack_slots(item) > 0 => return,
+        None => {
+            info!("{} can't be eaten", registry.name(item));
+            return;
+        }
+    };
+    if take_selected_item(player_ent, &mut inventory, &mut events).is_some() {
+        hunger.current = (hunger.current + consumable.nutrition).min(hunger.max);
+        health.current = (health.current + consumable.health).min(health.max);
+    }
+}
+
+/// Starving or freezing hurts, being fed and warm slowly heals
+fn take_damage(time: Res<Time>, mut stats_query: Query<(&mut Health, &Hunger, &Warmth)>) {
+    for (mut health, hunger, warmth) in stats_query.iter_mut() {
+        let mut change = 0.0;
+        if hunger.current <= 0.0 {
+            change -= STARVING_DAMAGE;
+        }
+        if warmth.current <= 0.0 {
+            change -= FREEZING_DAMAGE;
+        }
+        if hunger.current > hunger.max * COMFORTABLE && warmth.current > warmth.max * COMFORTABLE {
+            change += HEALTH_REGEN;
+        }
+        health.current = (health.current + change * time.delta_seconds()).clamp(0.0, health.max);
+    }
+}
+
+/// A dead player leaves everything they carried on the ground and starts over at the spawn point,
+/// the ingredients of a running craft are dropped with the rest
+fn die_and_respawn(
+    mut commands: Commands,
+    graphics: Res<PlaceHolderGraphics>,
+    registry: Res<ItemRegistry>,
+    crafting_book: Res<CraftingBook>,
+    mut events: InventoryEvents,
+    mut player_query: Query<
+        (
+            Entity,
+            &mut Transform,
+            &mut Inventory,
+            &mut Hand,
+            &mut Health,
+            &mut Hunger,
+            &mut Warmth,
+            Option<&CraftingJob>,
+        ),
+        With<Player>,
+    >,
+) {
+    let (
+        player_ent,
+        mut transform,
+        mut inventory,
+        mut hand,
+        mut health,
+        mut hunger,
+        mut warmth,
+        job,
+    ) = player_query.single_mut();
+    if health.current > 0.0 {
+        return;
+    }
+    info!("You died");
+    let position = transform.translation.truncate();
+    if let Some(job) = job {
+        for (item, count) in job.ingredients(&crafting_book) {
+            dropped_item(item, count, position, &mut commands, &graphics, &registry);
+        }
+        commands.entity(job.bar()).despawn_recursive();
+        commands.entity(player_ent).remove::<CraftingJob>();
+    }
+    let capacity = inventory.capacity();
+    for (i, slot) in inventory.items.iter_mut().enumerate() {
+        //Spread the drops in a circle so they don't all pile on one spot
+        let angle = i as f32 / capacity as f32 * std::f32::consts::TAU;
+        let spot = position + Vec2::new(angle.cos(), angle.sin()) * 30.0;
+        if slot.count > 0 {
+            dropped_item(
+                slot.item,
+                slot.count,
+                spot,
+                &mut commands,
+                &graphics,
+                &registry,
+            );
+            events.send_removed(player_ent, slot.item, slot.count);
+        }
+        *slot = InventoryEntry::default();
+    }
+    let held = std::mem::take(&mut hand.held);
+    if held.count > 0 {
+        dropped_item(
+            held.item,
+            held.count,
+            position,
+            &mut commands,
+            &graphics,
+            &registry,
+        );
+    }
+    *health = Health::default();
+    *hunger = Hunger::default();
+    *warmth = Warmth::default();
+    transform.translation.x = RESPAWN_POINT.x;
+    transform.translation.y = RESPAWN_POINT.y;
+}
+
+fn spawn_stat_bars(mut commands: Commands, camera_query: Query<Entity, With<GameCamera>>) {
+    let camera_ent = camera_query.single();
+    let bars = [
+        (Stat::Health, "Health", Color::rgb(0.8, 0.1, 0.1)),
+        (Stat::Hunger, "Hunger", Color::rgb(0.8, 0.5, 0.1)),
+        (Stat::Warmth, "Warmth", Color::rgb(0.9, 0.8, 0.2)),
+    ];
+    for (i, (stat, name, color)) in bars.into_iter().enumerate() {
+        let background = commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgba(0.0, 0.0, 0.0, 0.6),
+                    custom_size: Some(Vec2::new(BAR_WIDTH + 4.0, BAR_HEIGHT + 4.0)),
+                    ..Default::default()
+                },
+                transform: Transform::from_xyz(650.0, 420.0 - 24.0 * i as f32, -1.0),
+                ..Default::default()
+            })
+            .insert(Name::new(name))
+            .with_children(|parent| {
+                parent
+                    .spawn_bundle(SpriteBundle {
+                        sprite: Sprite {
+                            color: color,
+                            custom_size: Some(Vec2::new(BAR_WIDTH, BAR_HEIGHT)),
+                            ..Default::default()
+                        },
+                        transform: Transform::from_xyz(0.0, 0.0, 0.1),
+                        ..Default::default()
+                    })
+                    .insert(StatBar { stat: stat });
+            })
+            .id();
+        commands.entity(camera_ent).add_child(background);
+    }
+}
+
+fn update_stat_bars(
+    player_query: Query<(&Health, &Hunger, &Warmth), With<Player>>,
+    mut bar_query: Query<(&StatBar, &mut Sprite, &mut Transform)>,
+) {
+    let (health, hunger, warmth) = player_query.single();
+    for (bar, mut sprite, mut transform) in bar_query.iter_mut() {
+        let fill = match bar.stat {
+            Stat::Health => health.current / health.max,
+            Stat::Hunger => hunger.current / hunger.max,
+            Stat::Warmth => warmth.current / warmth.max,
+        };
+        sprite.custom_size = Some(Vec2::new(BAR_WIDTH * fill, BAR_HEIGHT));
+        //Keep the bar's left edge in place as it shrinks
+        transform.translation.x = -BAR_WIDTH * (1.0 - fill) / 2.0;
+    }
+}
@@ -0,0 +1,427 @@
+use bevy::{
+    ecs::system::CommandQueue,
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clock::{WorldClock, MAX_DARKNESS},
+    fire::Campfire,
+    graphics::PlaceHolderGraphics,
+    inventory::Inventory,
+    items::{
+        spawn_item, spawn_object, ItemType, NaturalSpawn, Object, Pickupable, SpawnCounts,
+        WorldObject, MAX_SPAWN_DISTANCE,
+    },
+    player::Player,
+    read_asset,
+    registry::ItemRegistry,
+    rng::GameRng,
+    save::{spawn_saved, ObjectSave, PickupableSave},
+};
+
+pub const WORLDGEN_PATH: &str = "worldgen.ron";
+/// Just above the clear color and below everything standing on the ground
+const GROUND_Z: f32 = -0.05;
+/// How much darker the ground is at night, like the clear color
+const NIGHT_SHADE: f32 = 0.7;
+/// Offsets the seed so each biome noise is different from the spawn rule noises
+const MOISTURE_LAYER: u64 = 0x6d6f_6973;
+const ROCKINESS_LAYER: u64 = 0x726f_636b;
+
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug, Serialize, Deserialize)]
+pub enum Biome {
+    Meadow,
+    Forest,
+    Rocky,
+    Swamp,
+}
+
+impl Biome {
+    pub const ALL: [Biome; 4] = [Biome::Meadow, Biome::Forest, Biome::Rocky, Biome::Swamp];
+
+    /// Wet regions are swamps and otherwise forests, dry ones are meadows unless rocky
+    fn from_noise(moisture: f32, rockiness: f32) -> Biome {
+        if moisture > 0.65 {
+            Biome::Swamp
+        } else if rockiness > 0.6 {
+            Biome::Rocky
+        } else if moisture > 0.48 {
+            Biome::Forest
+        } else {
+            Biome::Meadow
+        }
+    }
+}
+
+/// Places one kind of thing in every chunk, in patches following its noise
+#[derive(Deserialize)]
+pub struct GenRule {
+    pub spawns: WorldObject,
+    /// Spots tried in each chunk
+    pub per_chunk: u32,
+    /// Pixels between noise samples, bigger makes bigger patches
+    pub noise_scale: f32,
+    /// Noise value from 0 to 1 a spot needs to get something
+    pub threshold: f32,
+}
+
+#[derive(Deserialize)]
+pub struct BiomeDesc {
+    pub ground: (f32, f32, f32),
+    pub rules: Vec<GenRule>,
+    /// Most of an item natural spawning keeps in one chunk of this biome,
+    /// items without a cap are only held back by their max_alive
+    #[serde(default)]
+    pub caps: HashMap<ItemType, u32>,
+}
+
+impl BiomeDesc {
+    pub fn cap(&self, item: ItemType) -> Option<u32> {
+        self.caps.get(&item).copied()
+    }
+}
+
+#[derive(Deserialize)]
+pub struct WorldGen {
+    pub chunk_size: f32,
+    pub load_radius: i32,
+    pub unload_radius: i32,
+    pub spawn_clearing: f32,
+    /// Pixels between samples of the noises choosing biomes
+    pub biome_scale: f32,
+    biomes: HashMap<Biome, BiomeDesc>,
+}
+
+/// The tinted square under a loaded chunk
+#[derive(Component)]
+pub struct Ground {
+    chunk: IVec2,
+    tint: (f32, f32, f32),
+}
+
+/// What was in a chunk when it was put away
+#[derive(Default)]
+pub struct StoredChunk {
+    pub pickupables: Vec<PickupableSave>,
+    pub objects: Vec<ObjectSave>,
+}
+
+/// Chunks are generated only once, after that they are either loaded into the world
+/// or stored here with whatever the player changed in them
+#[derive(Default)]
+pub struct ChunkMap {
+    pub loaded: HashSet<IVec2>,
+    pub stored: HashMap<IVec2, StoredChunk>,
+}
+
+pub struct WorldGenPlugin;
+
+impl Plugin for WorldGenPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(load_world_gen(WORLDGEN_PATH))
+            .init_resource::<ChunkMap>()
+            .add_system(update_chunks.exclusive_system().label("chunks"))
+            .add_system(update_ground.label("ground").after("chunks"))
+            .add_system(shade_ground.after("ground").after("clock"));
+    }
+}
+
+pub fn load_world_gen(path: &str) -> WorldGen {
+    let contents = read_asset(path);
+    parse_world_gen(&contents)
+        .unwrap_or_else(|err| panic!("Invalid world generation file {}: {}", path, err))
+}
+
+pub fn parse_world_gen(contents: &str) -> Result<WorldGen, String> {
+    let gen: WorldGen = ron::de::from_str(contents).map_err(|err| err.to_string())?;
+    if gen.chunk_size <= 0.0 {
+        return Err("chunk_size has to be positive".to_string());
+    }
+    if gen.load_radius < 0 {
+        return Err("load_radius must not be negative".to_string());
+    }
+    if gen.unload_radius < gen.load_radius {
+        return Err("unload_radius can't be smaller than load_radius".to_string());
+    }
+    //Natural spawns have to land in loaded chunks so they get put away with them
+    if gen.load_radius as f32 * gen.chunk_size < MAX_SPAWN_DISTANCE {
+        return Err(format!(
+            "loaded chunks have to reach at least {} pixels out, as far as natural spawns go",
+            MAX_SPAWN_DISTANCE
+        ));
+    }
+    if gen.biome_scale <= 0.0 {
+        return Err("biome_scale has to be positive".to_string());
+    }
+    for biome in Biome::ALL {
+        let desc = gen
+            .biomes
+            .get(&biome)
+            .ok_or_else(|| format!("{:?} isn't described", biome))?;
+        for rule in desc.rules.iter() {
+            if rule.noise_scale <= 0.0 {
+                return Err(format!(
+                    "{:?} in {:?} has a noise_scale that isn't positive",
+                    rule.spawns, biome
+                ));
+            }
+            if !(0.0..=1.0).contains(&rule.threshold) {
+                return Err(format!(
+                    "{:?} in {:?} has a threshold outside 0 to 1",
+                    rule.spawns, biome
+                ));
+            }
+        }
+    }
+    Ok(gen)
+}
+
+impl WorldGen {
+    pub fn chunk_of(&self, position: Vec2) -> IVec2 {
+        (position / self.chunk_size).floor().as_ivec2()
+    }
+
+    /// Each chunk is one biome, picked from noise at its center so neighbours are often alike
+    pub fn biome_at(&self, seed: u64, chunk: IVec2) -> Biome {
+        let center = (chunk.as_vec2() + Vec2::splat(0.5)) * self.chunk_size / self.biome_scale;
+        Biome::from_noise(
+            value_noise(seed ^ MOISTURE_LAYER, center),
+            value_noise(seed ^ ROCKINESS_LAYER, center),
+        )
+    }
+
+    pub fn biome(&self, biome: Biome) -> &BiomeDesc {
+        //parse_world_gen made sure every biome is there
+        &self.biomes[&biome]
+    }
+
+    /// Everything a never visited chunk starts with, only depends on the seed
+    pub fn generate(&self, seed: u64, chunk: IVec2) -> Vec<(WorldObject, Vec2)> {
+        let mut rng = StdRng::seed_from_u64(hash(seed, chunk.x, chunk.y));
+        let corner = chunk.as_vec2() * self.chunk_size;
+        let rules = &self.biome(self.biome_at(seed, chunk)).rules;
+        let mut placed = Vec::new();
+        for (i, rule) in rules.iter().enumerate() {
+            let layer = seed.wrapping_add(i as u64 + 1);
+            for _ in 0..rule.per_chunk {
+                let position = corner
+                    + Vec2::new(
+                        rng.gen_range(0.0..self.chunk_size),
+                        rng.gen_range(0.0..self.chunk_size),
+                    );
+                if position.length() < self.spawn_clearing {
+                    continue;
+                }
+                if value_noise(layer, position / rule.noise_scale) >= rule.threshold {
+                    placed.push((rule.spawns, position));
+                }
+            }
+        }
+        placed
+    }
+}
+
+/// Mixes a seed and a lattice point into well spread bits (splitmix64)
+fn hash(seed: u64, x: i32, y: i32) -> u64 {
+    let mut z = seed ^ ((x as u32 as u64) << 32 | y as u32 as u64);
+    z = z.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Smooth noise from 0 to 1, random values on a grid blended in between
+pub fn value_noise(seed: u64, point: Vec2) -> f32 {
+    let cell = point.floor();
+    let fraction = point - cell;
+    let blend = fraction * fraction * (Vec2::splat(3.0) - 2.0 * fraction);
+    let (x, y) = (cell.x as i32, cell.y as i32);
+    let corner = |dx: i32, dy: i32| (hash(seed, x + dx, y + dy) >> 40) as f32 / (1u64 << 24) as f32;
+    let bottom = corner(0, 0) + (corner(1, 0) - corner(0, 0)) * blend.x;
+    let top = corner(0, 1) + (corner(1, 1) - corner(0, 1)) * blend.x;
+    bottom + (top - bottom) * blend.y
+}
+
+/// Brings chunks around the player into the world and puts far ones away
+fn update_chunks(world: &mut World) {
+    let mut player_query = world.query_filtered::<&Transform, With<Player>>();
+    let player_pos = match player_query.iter(world).next() {
+        Some(transform) => transform.translation.truncate(),
+        None => return,
+    };
+    let gen = world.get_resource::<WorldGen>().unwrap();
+    let center = gen.chunk_of(player_pos);
+    let (load_radius, unload_radius) = (gen.load_radius, gen.unload_radius);
+
+    let chunks = world.get_resource::<ChunkMap>().unwrap();
+    let far: Vec<IVec2> = chunks
+        .loaded
+        .iter()
+        .filter(|chunk| {
+            let offset = (**chunk - center).abs();
+            offset.x.max(offset.y) > unload_radius
+        })
+        .copied()
+        .collect();
+    let mut near = Vec::new();
+    for y in -load_radius..=load_radius {
+        for x in -load_radius..=load_radius {
+            let chunk = center + IVec2::new(x, y);
+            if !chunks.loaded.contains(&chunk) {
+                near.push(chunk);
+            }
+        }
+    }
+    if far.is_empty() && near.is_empty() {
+        return;
+    }
+
+    let put_away = store_chunks(world, &far);
+    let mut queue = CommandQueue::default();
+    let mut chunks = world.remove_resource::<ChunkMap>().unwrap();
+    let mut counts = world.remove_resource::<SpawnCounts>().unwrap();
+    {
+        let gen = world.get_resource::<WorldGen>().unwrap();
+        let seed = world.get_resource::<GameRng>().unwrap().seed();
+        let graphics = world.get_resource::<PlaceHolderGraphics>().unwrap();
+        let registry = world.get_resource::<ItemRegistry>().unwrap();
+        let mut commands = Commands::new(&mut queue, world);
+
+        for (chunk, (stored, entities)) in far.into_iter().zip(put_away) {
+            for ent in entities {
+                counts.put_away(ent);
+                commands.entity(ent).despawn_recursive();
+            }
+            chunks.loaded.remove(&chunk);
+            chunks.stored.insert(chunk, stored);
+        }
+        for chunk in near {
+            match chunks.stored.remove(&chunk) {
+                Some(stored) => spawn_saved(
+                    &stored.pickupables,
+                    &stored.objects,
+                    &mut commands,
+                    graphics,
+                    registry,
+                    &mut counts,
+                ),
+                None => {
+                    for (object, position) in gen.generate(seed, chunk) {
+                        match object {
+                            WorldObject::Item(item) => {
+                                spawn_item(item, position, &mut commands, graphics, registry)
+                            }
+                            object => spawn_object(object, position, &mut commands, graphics),
+                        };
+                    }
+                }
+            }
+            chunks.loaded.insert(chunk);
+        }
+    }
+    world.insert_resource(chunks);
+    world.insert_resource(counts);
+    queue.apply(world);
+}
+
+/// Snapshots everything lying in the given chunks, with the entities to despawn
+fn store_chunks(world: &mut World, chunks: &[IVec2]) -> Vec<(StoredChunk, Vec<Entity>)> {
+    let mut stored: Vec<(StoredChunk, Vec<Entity>)> = chunks
+        .iter()
+        .map(|_| (StoredChunk::default(), Vec::new()))
+        .collect();
+    if chunks.is_empty() {
+        return stored;
+    }
+    let gen = world.get_resource::<WorldGen>().unwrap();
+    let chunk_size = gen.chunk_size;
+    let mut query = world.query_filtered::<(
+        Entity,
+        &Transform,
+        Option<&Pickupable>,
+        Option<&Object>,
+        Option<&Campfire>,
+        Option<&Inventory>,
+        Option<&NaturalSpawn>,
+    ), Or<(With<Pickupable>, With<Object>)>>();
+    for (ent, transform, pickupable, object, campfire, inventory, natural) in query.iter(world) {
+        let position = transform.translation.truncate();
+        let chunk = (position / chunk_size).floor().as_ivec2();
+        let (contents, entities) = match chunks.iter().position(|c| *c == chunk) {
+            Some(i) => &mut stored[i],
+            None => continue,
+        };
+        let position = (position.x, position.y);
+        if let Some(pickupable) = pickupable {
+            contents.pickupables.push(PickupableSave {
+                item: pickupable.item,
+                count: pickupable.count,
+                position: position,
+                natural: natural.is_some(),
+            });
+        } else if let Some(object) = object {
+            contents.objects.push(ObjectSave {
+                object: object.object,
+                position: position,
+                fuel: campfire.map(|campfire| campfire.fuel),
+                inventory: inventory.cloned(),
+            });
+        }
+        entities.push(ent);
+    }
+    stored
+}
+
+/// Lays tinted ground under loaded chunks and lifts it from chunks put away
+fn update_ground(
+    mut commands: Commands,
+    gen: Res<WorldGen>,
+    rng: Res<GameRng>,
+    chunks: Res<ChunkMap>,
+    ground_query: Query<(Entity, &Ground)>,
+) {
+    let mut covered = HashSet::default();
+    for (ent, ground) in ground_query.iter() {
+        if chunks.loaded.contains(&ground.chunk) {
+            covered.insert(ground.chunk);
+        } else {
+            commands.entity(ent).despawn_recursive();
+        }
+    }
+    for chunk in chunks
+        .loaded
+        .iter()
+        .filter(|chunk| !covered.contains(*chunk))
+    {
+        let biome = gen.biome_at(rng.seed(), *chunk);
+        let (r, g, b) = gen.biome(biome).ground;
+        let center = (chunk.as_vec2() + Vec2::splat(0.5)) * gen.chunk_size;
+        commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgb(r, g, b),
+                    custom_size: Some(Vec2::splat(gen.chunk_size)),
+                    ..Default::default()
+                },
+                transform: Transform::from_translation(center.extend(GROUND_Z)),
+                ..Default::default()
+            })
+            .insert(Ground {
+                chunk: *chunk,
+                tint: (r, g, b),
+            })
+            .insert(Name::new(format!("{:?} Ground", biome)));
+    }
+}
+
+/// The ground covers the clear color, so it darkens with the night the same way
+fn shade_ground(clock: Res<WorldClock>, mut ground_query: Query<(&Ground, &mut Sprite)>) {
+    let shade = 1.0 - NIGHT_SHADE * clock.darkness() / MAX_DARKNESS;
+    for (ground, mut sprite) in ground_query.iter_mut() {
+        let (r, g, b) = ground.tint;
+        sprite.color = Color::rgb(r * shade, g * shade, b * shade);
+    }
+}
@@ -0,0 +1,852 @@
+//! Drives the game without a window: MinimalPlugins, stub graphics and keys pressed by hand
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use bevy::{
+    app::{Events, ManualEventReader},
+    ecs::system::{CommandQueue, Resource, SystemState},
+    prelude::*,
+    utils::HashMap,
+};
+use bevy_survival::{
+    chest::{ChestMenu, CHEST_SIZE},
+    clock::{parse_world_clock, DayPhase, PhaseChanged, WorldClock, CLOCK_PATH},
+    crafting::{CraftingJob, KnownRecipes},
+    graphics::PlaceHolderGraphics,
+    inventory::{
+        count_item, give_item, wear_selected_tool, Hand, Inventory, InventoryEvents, InventoryFull,
+        ItemAdded, ItemRemoved, Slot, SlotAction, SlotSelected,
+    },
+    items::{
+        dropped_item, spawn_object, ItemType, NaturalSpawn, Object, Pickupable, Tool, WorldObject,
+    },
+    player::Player,
+    read_asset,
+    registry::{parse_item_registry, ItemRegistry, ITEMS_PATH},
+    rng::GameRng,
+    save::{
+        load_game, parse_save, save_game, to_save_string, ChunkSave, ObjectSave, PickupableSave,
+        SaveGame,
+    },
+    survival::{Health, Hunger},
+    worldgen::{load_world_gen, parse_world_gen, Biome, ChunkMap, WORLDGEN_PATH},
+    GamePlugins, HeadlessPlugin,
+};
+
+const TEST_SEED: u64 = 1234;
+
+fn headless_app() -> App {
+    seeded_app(TEST_SEED)
+}
+
+fn seeded_app(seed: u64) -> App {
+    app_with(seed, |_| {})
+}
+
+/// Lets resources be put in before the plugins, which keep them instead of their defaults
+fn app_with(seed: u64, setup: impl FnOnce(&mut App)) -> App {
+    let mut app = App::new();
+    app.insert_resource(GameRng::new(seed));
+    setup(&mut app);
+    app.add_plugins(MinimalPlugins)
+        .add_plugin(HeadlessPlugin)
+        .add_plugins(GamePlugins);
+    //The first update runs the startup systems
+    app.update();
+    app
+}
+
+fn press(app: &mut App, key: KeyCode) {
+    app.world
+        .get_resource_mut::<Input<KeyCode>>()
+        .unwrap()
+        .press(key);
+    app.update();
+    let mut input = app.world.get_resource_mut::<Input<KeyCode>>().unwrap();
+    input.release(key);
+    input.clear();
+}
+
+fn slot_action(app: &mut App, action: SlotAction) {
+    app.world
+        .get_resource_mut::<Events<SlotAction>>()
+        .unwrap()
+        .send(action);
+    app.update();
+}
+
+fn player(app: &mut App) -> Entity {
+    app.world
+        .query_filtered::<Entity, With<Player>>()
+        .iter(&app.world)
+        .next()
+        .unwrap()
+}
+
+fn player_position(app: &mut App) -> Vec2 {
+    let player = player(app);
+    app.world
+        .get::<Transform>(player)
+        .unwrap()
+        .translation
+        .truncate()
+}
+
+fn inventory(app: &mut App) -> Inventory {
+    let player = player(app);
+    app.world.get::<Inventory>(player).unwrap().clone()
+}
+
+fn place_item(app: &mut App, item: ItemType, position: Vec2) {
+    place_stack(app, item, 1, position);
+}
+
+fn place_stack(app: &mut App, item: ItemType, count: usize, position: Vec2) {
+    let mut queue = CommandQueue::default();
+    {
+        let graphics = app.world.get_resource::<PlaceHolderGraphics>().unwrap();
+        let registry = app.world.get_resource::<ItemRegistry>().unwrap();
+        let mut commands = Commands::new(&mut queue, &app.world);
+        dropped_item(item, count, position, &mut commands, graphics, registry);
+    }
+    queue.apply(&mut app.world);
+}
+
+/// Gives straight to the player's inventory, returns what didn't fit
+fn give(app: &mut App, item: ItemType, amount: usize) -> usize {
+    let player = player(app);
+    let mut state: SystemState<(Query<&mut Inventory>, Res<ItemRegistry>, InventoryEvents)> =
+        SystemState::new(&mut app.world);
+    let (mut inventory_query, registry, mut events) = state.get_mut(&mut app.world);
+    let mut inventory = inventory_query.get_mut(player).unwrap();
+    give_item(player, &mut inventory, item, amount, &registry, &mut events)
+}
+
+/// Places a chest within reach and opens it with the interact key
+fn open_chest_beside_player(app: &mut App) -> Entity {
+    let spot = player_position(app) + Vec2::new(20.0, 0.0);
+    let mut queue = CommandQueue::default();
+    {
+        let graphics = app.world.get_resource::<PlaceHolderGraphics>().unwrap();
+        let mut commands = Commands::new(&mut queue, &app.world);
+        spawn_object(WorldObject::Chest, spot, &mut commands, graphics);
+    }
+    queue.apply(&mut app.world);
+    app.update();
+    press(app, KeyCode::F);
+    app.world
+        .get_resource::<ChestMenu>()
+        .unwrap()
+        .chest
+        .expect("the chest should be open")
+}
+
+/// Events of one kind sent since the reader last looked
+fn new_events<T: Resource, U>(
+    app: &App,
+    reader: &mut ManualEventReader<T>,
+    map: impl Fn(&T) -> U,
+) -> Vec<U> {
+    let events = app.world.get_resource::<Events<T>>().unwrap();
+    reader.iter(events).map(map).collect()
+}
+
+/// Only counts near a spot so generated items elsewhere don't get in the way
+fn items_near(app: &mut App, item: ItemType, position: Vec2) -> usize {
+    app.world
+        .query::<(&Pickupable, &Transform)>()
+        .iter(&app.world)
+        .filter(|(pickupable, transform)| {
+            pickupable.item == item && transform.translation.truncate().distance(position) < 1.0
+        })
+        .count()
+}
+
+#[test]
+fn pick_up_flint() {
+    let mut app = headless_app();
+    let position = player_position(&mut app);
+    place_item(&mut app, ItemType::Flint, position);
+    assert_eq!(items_near(&mut app, ItemType::Flint, position), 1);
+
+    press(&mut app, KeyCode::E);
+    app.update();
+
+    assert_eq!(count_item(&inventory(&mut app), ItemType::Flint), 1);
+    assert_eq!(items_near(&mut app, ItemType::Flint, position), 0);
+}
+
+#[test]
+fn craft_axe() {
+    let mut app = headless_app();
+    let position = player_position(&mut app);
+    place_item(&mut app, ItemType::Twig, position);
+    place_item(&mut app, ItemType::Flint, position);
+    press(&mut app, KeyCode::E);
+    app.update();
+
+    let axe = WorldObject::Item(ItemType::Tool(Tool::Axe));
+    let player = player(&mut app);
+    assert!(app
+        .world
+        .get::<KnownRecipes>(player)
+        .unwrap()
+        .known
+        .contains(&axe));
+
+    //Open the menu and craft the only known recipe
+    press(&mut app, KeyCode::C);
+    press(&mut app, KeyCode::Return);
+    assert!(app.world.get::<CraftingJob>(player).is_some());
+    assert_eq!(count_item(&inventory(&mut app), ItemType::Flint), 0);
+
+    //Escape closes an open chest first and leaves the craft running
+    open_chest_beside_player(&mut app);
+    press(&mut app, KeyCode::Escape);
+    assert!(app
+        .world
+        .get_resource::<ChestMenu>()
+        .unwrap()
+        .chest
+        .is_none());
+    assert!(app.world.get::<CraftingJob>(player).is_some());
+
+    //Time can't be faked, so let the craft run in real time
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while app.world.get::<CraftingJob>(player).is_some() && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(10));
+        app.update();
+    }
+    let inventory = inventory(&mut app);
+    assert_eq!(count_item(&inventory, ItemType::Tool(Tool::Axe)), 1);
+    assert_eq!(count_item(&inventory, ItemType::Twig), 0);
+}
+
+#[test]
+fn dying_drops_a_running_craft() {
+    let mut app = headless_app();
+    let position = player_position(&mut app);
+    place_item(&mut app, ItemType::Twig, position);
+    place_item(&mut app, ItemType::Flint, position);
+    press(&mut app, KeyCode::E);
+    app.update();
+    press(&mut app, KeyCode::C);
+    press(&mut app, KeyCode::Return);
+    let player = player(&mut app);
+    assert!(app.world.get::<CraftingJob>(player).is_some());
+
+    //Starving so health can't regenerate before the death is noticed
+    app.world.get_mut::<Hunger>(player).unwrap().current = 0.0;
+    app.world.get_mut::<Health>(player).unwrap().current = 0.0;
+    app.update();
+    app.update();
+    assert!(app.world.get::<CraftingJob>(player).is_none());
+    //The ingredients were in the craft, they end up on the ground with everything else
+    let near_death = |app: &mut App, item: ItemType| {
+        app.world
+            .query::<(&Pickupable, &Transform)>()
+            .iter(&app.world)
+            .filter(|(pickupable, transform)| {
+                pickupable.item == item
+                    && transform.translation.truncate().distance(position) < 100.0
+            })
+            .count()
+    };
+    for item in [ItemType::Twig, ItemType::Flint] {
+        assert_eq!(count_item(&inventory(&mut app), item), 0);
+        assert_eq!(near_death(&mut app, item), 1);
+    }
+}
+
+#[test]
+fn stacks_overflow_and_stay_partly_on_the_ground() {
+    let mut app = headless_app();
+    //Wood stacks to 20, so 385 fills the hotbar, nine backpack slots and a bit of the last one
+    assert_eq!(inventory(&mut app).capacity(), 20);
+    assert_eq!(give(&mut app, ItemType::Wood, 385), 0);
+    assert_eq!(inventory(&mut app).items[19].count, 5);
+    assert_eq!(give(&mut app, ItemType::Wood, 20), 5);
+    assert_eq!(count_item(&inventory(&mut app), ItemType::Wood), 400);
+
+    //Make room for one stack of flint and lay down more than that
+    let player = player(&mut app);
+    app.world.get_mut::<Inventory>(player).unwrap().items[3] = Default::default();
+    let position = player_position(&mut app);
+    place_stack(&mut app, ItemType::Flint, 25, position);
+    press(&mut app, KeyCode::E);
+    app.update();
+
+    assert_eq!(count_item(&inventory(&mut app), ItemType::Flint), 20);
+    let left: Vec<usize> = app
+        .world
+        .query::<(&Pickupable, &Transform)>()
+        .iter(&app.world)
+        .filter(|(pickupable, transform)| {
+            pickupable.item == ItemType::Flint
+                && transform.translation.truncate().distance(position) < 1.0
+        })
+        .map(|(pickupable, _)| pickupable.count)
+        .collect();
+    assert_eq!(left, vec![5]);
+}
+
+#[test]
+fn mouse_moves_splits_and_throws() {
+    let mut app = headless_app();
+    give(&mut app, ItemType::Twig, 7);
+    give(&mut app, ItemType::Flint, 3);
+    let player = player(&mut app);
+    let own = |i: usize| Slot {
+        owner: player,
+        index: i,
+    };
+    let slot = |app: &mut App, i: usize| {
+        let entry = &inventory(app).items[i];
+        (entry.item, entry.count)
+    };
+
+    //Drag the twigs into an empty slot
+    slot_action(&mut app, SlotAction::Press(Some(own(0))));
+    slot_action(&mut app, SlotAction::Release(Some(own(2))));
+    assert_eq!(slot(&mut app, 0), (ItemType::None, 0));
+    assert_eq!(slot(&mut app, 2), (ItemType::Twig, 7));
+
+    //Dragging onto the flint swaps them, the flint stays in hand until put down
+    slot_action(&mut app, SlotAction::Press(Some(own(2))));
+    slot_action(&mut app, SlotAction::Release(Some(own(1))));
+    assert_eq!(slot(&mut app, 1), (ItemType::Twig, 7));
+    slot_action(&mut app, SlotAction::Press(Some(own(2))));
+    assert_eq!(slot(&mut app, 2), (ItemType::Flint, 3));
+
+    //Split the twigs and throw the bigger half away
+    slot_action(&mut app, SlotAction::Split(own(1)));
+    assert_eq!(app.world.get::<Hand>(player).unwrap().held.count, 4);
+    slot_action(&mut app, SlotAction::Press(None));
+    app.update();
+    assert_eq!(count_item(&inventory(&mut app), ItemType::Twig), 3);
+    let position = player_position(&mut app);
+    assert_eq!(items_near(&mut app, ItemType::Twig, position), 1);
+
+    //Shift click sends the flint to the backpack and back to the first free hotbar slot
+    slot_action(&mut app, SlotAction::QuickMove(own(2)));
+    assert_eq!(slot(&mut app, 10), (ItemType::Flint, 3));
+    assert_eq!(slot(&mut app, 2), (ItemType::None, 0));
+    slot_action(&mut app, SlotAction::QuickMove(own(10)));
+    assert_eq!(slot(&mut app, 0), (ItemType::Flint, 3));
+}
+
+#[test]
+fn bags_grow_the_backpack() {
+    let mut app = headless_app();
+    for capacity in [30, 40, 50] {
+        give(&mut app, ItemType::Bag, 1);
+        press(&mut app, KeyCode::R);
+        let inventory = inventory(&mut app);
+        assert_eq!(inventory.capacity(), capacity);
+        assert_eq!(count_item(&inventory, ItemType::Bag), 0);
+    }
+
+    //A full size backpack keeps the bag
+    give(&mut app, ItemType::Bag, 1);
+    press(&mut app, KeyCode::R);
+    let inventory = inventory(&mut app);
+    assert_eq!(inventory.capacity(), 50);
+    assert_eq!(count_item(&inventory, ItemType::Bag), 1);
+}
+
+#[test]
+fn drop_item() {
+    let mut app = headless_app();
+    give(&mut app, ItemType::Twig, 1);
+    assert_eq!(inventory(&mut app).selected_item(), ItemType::Twig);
+
+    press(&mut app, KeyCode::Q);
+    app.update();
+
+    assert_eq!(count_item(&inventory(&mut app), ItemType::Twig), 0);
+    let position = player_position(&mut app);
+    assert_eq!(items_near(&mut app, ItemType::Twig, position), 1);
+}
+
+fn reparse(app: &App, saved: &str) -> Result<SaveGame, String> {
+    parse_save(saved, app.world.get_resource::<ItemRegistry>().unwrap())
+}
+
+#[test]
+fn save_round_trip() {
+    let mut app = headless_app();
+    let player = player(&mut app);
+    give(&mut app, ItemType::Wood, 1);
+    let flint_spot = Vec2::new(300.0, 200.0);
+    place_item(&mut app, ItemType::Flint, flint_spot);
+    app.update();
+    let saved = to_save_string(&save_game(&mut app.world).unwrap()).unwrap();
+
+    //Change everything the save should bring back
+    press(&mut app, KeyCode::Q);
+    app.world
+        .get_mut::<Transform>(player)
+        .unwrap()
+        .translation
+        .x = 500.0;
+    app.update();
+    assert_eq!(count_item(&inventory(&mut app), ItemType::Wood), 0);
+
+    let save = reparse(&app, &saved).unwrap();
+    load_game(&mut app.world, save).unwrap();
+    app.update();
+
+    assert_eq!(count_item(&inventory(&mut app), ItemType::Wood), 1);
+    assert_eq!(items_near(&mut app, ItemType::Wood, Vec2::ZERO), 0);
+    assert_eq!(items_near(&mut app, ItemType::Flint, flint_spot), 1);
+    assert_eq!(player_position(&mut app), Vec2::ZERO);
+    let reloaded = save_game(&mut app.world).unwrap();
+    assert_eq!(
+        reloaded.objects.len(),
+        reparse(&app, &saved).unwrap().objects.len()
+    );
+}
+
+fn object_positions(app: &mut App) -> Vec<(WorldObject, Vec2)> {
+    app.world
+        .query::<(&Object, &Transform)>()
+        .iter(&app.world)
+        .map(|(object, transform)| (object.object, transform.translation.truncate()))
+        .collect()
+}
+
+#[test]
+fn same_seed_same_world() {
+    let first = object_positions(&mut seeded_app(7));
+    assert!(!first.is_empty());
+    assert_eq!(object_positions(&mut seeded_app(7)), first);
+    assert_ne!(object_positions(&mut seeded_app(8)), first);
+}
+
+#[test]
+fn chunks_remember_changes() {
+    let mut app = headless_app();
+    let generated = object_positions(&mut app).len();
+    let twig_spot = Vec2::new(200.0, 0.0);
+    place_item(&mut app, ItemType::Twig, twig_spot);
+
+    //Walk far enough for the spawn chunks to be put away
+    let player = player(&mut app);
+    app.world
+        .get_mut::<Transform>(player)
+        .unwrap()
+        .translation
+        .x = 10000.0;
+    app.update();
+    assert_eq!(items_near(&mut app, ItemType::Twig, twig_spot), 0);
+
+    app.world
+        .get_mut::<Transform>(player)
+        .unwrap()
+        .translation
+        .x = 0.0;
+    app.update();
+    assert_eq!(items_near(&mut app, ItemType::Twig, twig_spot), 1);
+    assert_eq!(object_positions(&mut app).len(), generated);
+}
+
+#[test]
+fn chests_store_stacks_and_are_saved() {
+    let mut app = headless_app();
+    let player = player(&mut app);
+    give(&mut app, ItemType::Flint, 15);
+    let chest = open_chest_beside_player(&mut app);
+    let own = Slot {
+        owner: player,
+        index: 0,
+    };
+    let stored = Slot {
+        owner: chest,
+        index: 4,
+    };
+
+    //Shift click moves the whole stack over, dragging brings some back
+    slot_action(&mut app, SlotAction::QuickMove(own));
+    let chest_flint =
+        |app: &mut App| count_item(app.world.get::<Inventory>(chest).unwrap(), ItemType::Flint);
+    assert_eq!(chest_flint(&mut app), 15);
+    assert_eq!(count_item(&inventory(&mut app), ItemType::Flint), 0);
+    slot_action(&mut app, SlotAction::QuickMove(own));
+    let chest_slot = Slot {
+        owner: chest,
+        index: 0,
+    };
+    slot_action(&mut app, SlotAction::Split(chest_slot));
+    slot_action(&mut app, SlotAction::Press(Some(stored)));
+    assert_eq!(app.world.get::<Inventory>(chest).unwrap().items[4].count, 8);
+    slot_action(&mut app, SlotAction::Press(Some(stored)));
+    slot_action(&mut app, SlotAction::Release(Some(own)));
+    assert_eq!(inventory(&mut app).items[0].count, 8);
+    assert_eq!(chest_flint(&mut app), 7);
+
+    let saved = to_save_string(&save_game(&mut app.world).unwrap()).unwrap();
+    app.world.get_mut::<Inventory>(chest).unwrap().items.clear();
+    let save = reparse(&app, &saved).unwrap();
+    load_game(&mut app.world, save).unwrap();
+    app.update();
+    let chests: Vec<usize> = app
+        .world
+        .query::<(&Object, &Inventory)>()
+        .iter(&app.world)
+        .filter(|(object, _)| object.object == WorldObject::Chest)
+        .map(|(_, inventory)| count_item(inventory, ItemType::Flint))
+        .collect();
+    assert_eq!(chests, vec![7]);
+
+    //Walking away closes it
+    press(&mut app, KeyCode::F);
+    assert!(app
+        .world
+        .get_resource::<ChestMenu>()
+        .unwrap()
+        .chest
+        .is_some());
+    app.world
+        .get_mut::<Transform>(player)
+        .unwrap()
+        .translation
+        .x += 500.0;
+    app.update();
+    assert!(app
+        .world
+        .get_resource::<ChestMenu>()
+        .unwrap()
+        .chest
+        .is_none());
+}
+
+#[test]
+fn impossible_saves_are_refused() {
+    let mut app = headless_app();
+    open_chest_beside_player(&mut app);
+    let saved = to_save_string(&save_game(&mut app.world).unwrap()).unwrap();
+    assert!(reparse(&app, &saved).is_ok());
+    let too_many = app
+        .world
+        .get_resource::<ItemRegistry>()
+        .unwrap()
+        .stack_size(ItemType::Flint)
+        + 1;
+    let overfill = |inventory: &mut Inventory| {
+        inventory.items[0].item = ItemType::Flint;
+        inventory.items[0].count = too_many;
+    };
+
+    let refused = |change: &dyn Fn(&mut SaveGame)| {
+        let mut save = reparse(&app, &saved).unwrap();
+        change(&mut save);
+        reparse(&app, &to_save_string(&save).unwrap()).is_err()
+    };
+
+    assert!(refused(&|save| overfill(&mut save.player.inventory)));
+    assert!(refused(&|save| overfill(chest_inventory(
+        &mut save.objects
+    ))));
+    assert!(refused(&|save| {
+        *chest_inventory(&mut save.objects) = Inventory::new(CHEST_SIZE + 1)
+    }));
+    //Chests in chunks that are put away are checked too
+    assert!(refused(&|save| {
+        let mut objects = save.objects.clone();
+        overfill(chest_inventory(&mut objects));
+        save.stored_chunks.push(ChunkSave {
+            chunk: (50, 50),
+            pickupables: Vec::new(),
+            objects,
+        });
+    }));
+
+    let lying = |item: ItemType, count: usize| PickupableSave {
+        item,
+        count,
+        position: (300.0, 0.0),
+        natural: false,
+    };
+    assert!(refused(&|save| save
+        .pickupables
+        .push(lying(ItemType::None, 1))));
+    assert!(refused(&|save| save
+        .pickupables
+        .push(lying(ItemType::Flint, 0))));
+    assert!(refused(&|save| {
+        save.stored_chunks.push(ChunkSave {
+            chunk: (50, 50),
+            pickupables: vec![lying(ItemType::Flint, too_many)],
+            objects: Vec::new(),
+        });
+    }));
+    assert!(refused(&|save| save.objects[0].fuel = Some(f32::NAN)));
+    assert!(refused(&|save| save.objects[0].fuel = Some(-1.0)));
+    assert!(refused(&|save| save.clock.time = 1.5));
+}
+
+fn chest_inventory(objects: &mut [ObjectSave]) -> &mut Inventory {
+    objects
+        .iter_mut()
+        .find(|object| object.object == WorldObject::Chest)
+        .and_then(|object| object.inventory.as_mut())
+        .unwrap()
+}
+
+#[test]
+fn inventory_changes_send_events() {
+    let mut app = headless_app();
+    let player = player(&mut app);
+    let mut added = ManualEventReader::<ItemAdded>::default();
+    let mut removed = ManualEventReader::<ItemRemoved>::default();
+    let mut selected = ManualEventReader::<SlotSelected>::default();
+    let mut full = ManualEventReader::<InventoryFull>::default();
+    let change = |event: &ItemAdded| (event.container, event.item, event.count);
+    let taken = |event: &ItemRemoved| (event.container, event.item, event.count);
+
+    let position = player_position(&mut app);
+    place_stack(&mut app, ItemType::Flint, 4, position);
+    press(&mut app, KeyCode::E);
+    assert_eq!(
+        new_events(&app, &mut added, change),
+        vec![(player, ItemType::Flint, 4)]
+    );
+
+    press(&mut app, KeyCode::Key3);
+    press(&mut app, KeyCode::Key3);
+    assert_eq!(new_events(&app, &mut selected, |event| event.slot), vec![2]);
+
+    //Starting a craft takes its ingredients through remove_item
+    give(&mut app, ItemType::Twig, 1);
+    press(&mut app, KeyCode::C);
+    press(&mut app, KeyCode::Return);
+    press(&mut app, KeyCode::C);
+    assert_eq!(
+        new_events(&app, &mut removed, taken),
+        vec![(player, ItemType::Twig, 1), (player, ItemType::Flint, 1)]
+    );
+
+    //Dropping, eating and a tool breaking all take something away,
+    //events only last two frames so each is checked right away
+    press(&mut app, KeyCode::Key1);
+    press(&mut app, KeyCode::Q);
+    assert_eq!(
+        new_events(&app, &mut removed, taken),
+        vec![(player, ItemType::Flint, 1)]
+    );
+    give(&mut app, ItemType::PineCone, 1);
+    press(&mut app, KeyCode::Key2);
+    press(&mut app, KeyCode::R);
+    assert_eq!(
+        new_events(&app, &mut removed, taken),
+        vec![(player, ItemType::PineCone, 1)]
+    );
+    let axe = ItemType::Tool(Tool::Axe);
+    give(&mut app, axe, 1);
+    let durability = app
+        .world
+        .get_resource::<ItemRegistry>()
+        .unwrap()
+        .durability(axe)
+        .unwrap();
+    app.world.get_mut::<Inventory>(player).unwrap().items[1].used = durability - 1;
+    let mut state: SystemState<(Query<&mut Inventory>, Res<ItemRegistry>, InventoryEvents)> =
+        SystemState::new(&mut app.world);
+    let (mut inventory_query, registry, mut events) = state.get_mut(&mut app.world);
+    let mut axe_holder = inventory_query.get_mut(player).unwrap();
+    wear_selected_tool(player, &mut axe_holder, &registry, &mut events);
+    assert_eq!(
+        new_events(&app, &mut removed, taken),
+        vec![(player, axe, 1)]
+    );
+    assert_eq!(count_item(&inventory(&mut app), axe), 0);
+
+    //Shift clicking into a chest takes from one container and adds to the other
+    //Skip everything given so far
+    new_events(&app, &mut added, change);
+    let chest = open_chest_beside_player(&mut app);
+    let flint = Slot {
+        owner: player,
+        index: 0,
+    };
+    slot_action(&mut app, SlotAction::QuickMove(flint));
+    assert_eq!(
+        new_events(&app, &mut removed, taken),
+        vec![(player, ItemType::Flint, 2)]
+    );
+    assert_eq!(
+        new_events(&app, &mut added, change),
+        vec![(chest, ItemType::Flint, 2)]
+    );
+
+    //Dragging a stack back out of the chest goes through the hand
+    slot_action(
+        &mut app,
+        SlotAction::Press(Some(Slot {
+            owner: chest,
+            index: 0,
+        })),
+    );
+    slot_action(&mut app, SlotAction::Release(Some(flint)));
+    assert_eq!(
+        new_events(&app, &mut removed, taken),
+        vec![(chest, ItemType::Flint, 2)]
+    );
+    assert_eq!(
+        new_events(&app, &mut added, change),
+        vec![(player, ItemType::Flint, 2)]
+    );
+
+    let left = give(&mut app, ItemType::Wood, 1000);
+    assert!(left > 0);
+    assert_eq!(new_events(&app, &mut full, |event| event.left), vec![left]);
+}
+
+/// Every spawn rule fires each frame so caps are reached without waiting
+fn eager_spawner(max_alive: Option<u32>) -> ItemRegistry {
+    let eager: Vec<String> = read_asset(ITEMS_PATH)
+        .lines()
+        .map(
+            |line| match (line.trim_start().split(':').next(), max_alive) {
+                (Some("per_second"), _) => "per_second: 1000.0,".to_string(),
+                (Some("max_alive"), Some(max_alive)) => format!("max_alive: {},", max_alive),
+                _ => line.to_string(),
+            },
+        )
+        .collect();
+    parse_item_registry(&eager.join("\n")).unwrap()
+}
+
+/// Naturally spawned ones in the world and in stored chunks
+fn natural_count(app: &mut App, item: ItemType) -> u32 {
+    let loaded = app
+        .world
+        .query_filtered::<&Pickupable, With<NaturalSpawn>>()
+        .iter(&app.world)
+        .filter(|pickupable| pickupable.item == item)
+        .count();
+    let chunks = app.world.get_resource::<ChunkMap>().unwrap();
+    let stored = chunks
+        .stored
+        .values()
+        .flat_map(|chunk| chunk.pickupables.iter())
+        .filter(|pickupable| pickupable.natural && pickupable.item == item)
+        .count();
+    (loaded + stored) as u32
+}
+
+#[test]
+fn natural_spawns_stay_under_max_alive() {
+    let mut app = headless_app();
+    let registry = eager_spawner(Some(3));
+    let rules: Vec<(ItemType, u32)> = registry
+        .iter()
+        .filter_map(|desc| Some((desc.id, desc.natural_spawn.as_ref()?.max_alive)))
+        .collect();
+    app.insert_resource(registry);
+
+    let player = player(&mut app);
+    let mut reached_cap = false;
+    for x in [0.0, 10000.0, 0.0] {
+        app.world
+            .get_mut::<Transform>(player)
+            .unwrap()
+            .translation
+            .x = x;
+        for _ in 0..200 {
+            app.update();
+            for (item, max_alive) in rules.iter() {
+                let count = natural_count(&mut app, *item);
+                assert!(count <= *max_alive, "{:?}: {} spawned", item, count);
+                reached_cap |= count == *max_alive;
+            }
+        }
+    }
+    assert!(reached_cap);
+}
+
+#[test]
+fn biomes_follow_the_seed_and_keep_their_caps() {
+    let mut app = headless_app();
+    let gen = load_world_gen(WORLDGEN_PATH);
+    //Natural spawns would land outside the loaded chunks
+    let small = read_asset(WORLDGEN_PATH).replace("load_radius: 2", "load_radius: 1");
+    assert!(parse_world_gen(&small).is_err());
+    for (chunk, biome) in [
+        ((0, 0), Biome::Meadow),
+        ((3, 0), Biome::Rocky),
+        ((0, 2), Biome::Forest),
+        ((0, 3), Biome::Swamp),
+    ] {
+        let chunk = IVec2::new(chunk.0, chunk.1);
+        assert_eq!(gen.biome_at(TEST_SEED, chunk), biome);
+        let rules = &gen.biome(biome).rules;
+        for (object, _) in gen.generate(TEST_SEED, chunk) {
+            assert!(rules.iter().any(|rule| rule.spawns == object));
+        }
+    }
+
+    //Generation may go over a cap, natural spawning never adds to a chunk that is
+    app.insert_resource(eager_spawner(None));
+    for _ in 0..300 {
+        app.update();
+        let mut in_chunks: HashMap<(IVec2, ItemType), (u32, u32)> = HashMap::default();
+        for (transform, pickupable, natural) in app
+            .world
+            .query::<(&Transform, &Pickupable, Option<&NaturalSpawn>)>()
+            .iter(&app.world)
+        {
+            let chunk = gen.chunk_of(transform.translation.truncate());
+            let (all, spawned) = in_chunks.entry((chunk, pickupable.item)).or_default();
+            *all += 1;
+            *spawned += natural.is_some() as u32;
+        }
+        for ((chunk, item), (all, spawned)) in in_chunks {
+            if let Some(cap) = gen.biome(gen.biome_at(TEST_SEED, chunk)).cap(item) {
+                assert!(
+                    spawned == 0 || all <= cap,
+                    "{} {:?} in {}",
+                    all,
+                    item,
+                    chunk
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn clock_announces_phase_changes() {
+    let clock_file = read_asset(CLOCK_PATH);
+    for broken in [
+        clock_file.replace("day_length: 300.0", "day_length: 0.0"),
+        clock_file.replace("dusk_start: 0.7", "dusk_start: 0.25"),
+        clock_file.replace("night_start: 0.8", "night_start: 1.5"),
+    ] {
+        assert!(parse_world_clock(&broken).is_err());
+    }
+
+    //A short day started just before dusk
+    let mut clock = parse_world_clock(&clock_file).unwrap();
+    clock.day_length = 1.0;
+    clock.set_time(clock.dusk_start - 0.001, 0);
+    let mut app = app_with(TEST_SEED, |app| {
+        app.insert_resource(clock);
+    });
+    assert_eq!(
+        app.world.get_resource::<WorldClock>().unwrap().day_length,
+        1.0
+    );
+
+    let mut changes = ManualEventReader::<PhaseChanged>::default();
+    let mut phases = Vec::new();
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while phases.is_empty() && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(5));
+        app.update();
+        phases = new_events(&app, &mut changes, |event| event.phase);
+    }
+    assert_eq!(phases, vec![DayPhase::Dusk]);
+}